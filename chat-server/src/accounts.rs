@@ -0,0 +1,129 @@
+extern crate ring;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::{File, OpenOptions};
+use std::num::NonZeroU32;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use std::collections::HashMap;
+
+use self::ring::pbkdf2;
+use self::ring::rand::{SecureRandom, SystemRandom};
+
+
+const ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+
+// a registered nickname with its salted PBKDF2-HMAC-SHA256 password hash
+#[derive(Clone)]
+pub struct Account {
+    pub nick: String,
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl Account {
+    // hashing is slow on purpose, so it is done before taking any lock
+    pub fn create (nick: &str, password: &str) -> Account {
+        let mut salt = vec![0; SALT_LENGTH];
+        SystemRandom::new().fill(&mut salt).unwrap();
+        let mut hash = vec![0; HASH_LENGTH];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, NonZeroU32::new(ITERATIONS).unwrap(),
+                       &salt, password.as_bytes(), &mut hash);
+        Account { nick: nick.to_string(), iterations: ITERATIONS, salt, hash }
+    }
+
+    pub fn verify (&self, password: &str) -> bool {
+        match NonZeroU32::new(self.iterations) {
+            Some(iterations) => pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, iterations,
+                                               &self.salt, password.as_bytes(), &self.hash).is_ok(),
+            None => false,
+        }
+    }
+
+    // nick, iterations, salt and hash separated by tabs
+    fn to_line (&self) -> String {
+        format!("{}\t{}\t{}\t{}\n", self.nick, self.iterations, to_hex(&self.salt), to_hex(&self.hash))
+    }
+
+    fn from_line (line: &str) -> Option<Account> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            [nick, iterations, salt, hash] => Some(Account {
+                nick: nick.to_string(),
+                iterations: iterations.parse().ok()?,
+                salt: from_hex(salt)?,
+                hash: from_hex(hash)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn to_hex (bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex (text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+
+pub struct Accounts {
+    path: Option<PathBuf>,
+    accounts: HashMap<String, Account>,
+}
+
+impl Accounts {
+    // without a file nobody can register
+    pub fn disabled () -> Accounts {
+        Accounts { path: None, accounts: HashMap::new() }
+    }
+
+    pub fn open (path: &Path) -> io::Result<Accounts> {
+        let mut accounts = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(account) = Account::from_line(&line?) {
+                    accounts.insert(account.nick.clone(), account);
+                }
+            }
+        }
+        Ok(Accounts { path: Some(path.to_path_buf()), accounts })
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn get (&self, nick: &str) -> Option<&Account> {
+        self.accounts.get(nick)
+    }
+
+    pub fn is_registered (&self, nick: &str) -> bool {
+        self.accounts.contains_key(nick)
+    }
+
+    pub fn add (&mut self, account: Account) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Err("registration is disabled on this server".to_string()),
+        };
+        if self.is_registered(&account.nick) {
+            return Err(format!("nickname {} is already registered", account.nick));
+        }
+        OpenOptions::new().create(true).append(true).mode(0o600).open(path)
+            .and_then(|mut file| file.write_all(account.to_line().as_bytes()))
+            .map_err(|e| format!("cannot save account: {}", e))?;
+        self.accounts.insert(account.nick.clone(), account);
+        Ok(())
+    }
+}
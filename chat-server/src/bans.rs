@@ -0,0 +1,105 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::File;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+pub struct Ban {
+    pub address: IpAddr,
+    // seconds since the epoch, or None for a ban which lasts until /unban
+    expires: Option<u64>,
+    pub reason: String,
+}
+
+impl Ban {
+    fn is_active (&self, now: u64) -> bool {
+        self.expires.map(|expires| now < expires).unwrap_or(true)
+    }
+
+    // address, expiry (0 for never) and reason separated by tabs
+    fn to_line (&self) -> String {
+        format!("{}\t{}\t{}\n", self.address, self.expires.unwrap_or(0), self.reason)
+    }
+
+    fn from_line (line: &str) -> Option<Ban> {
+        let mut fields = line.splitn(3, '\t');
+        let address = fields.next()?.parse().ok()?;
+        let expires = match fields.next()?.parse().ok()? {
+            0 => None,
+            time => Some(time),
+        };
+        let reason = fields.next().unwrap_or("").to_string();
+        Some(Ban { address, expires, reason })
+    }
+}
+
+fn now () -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+
+// banned addresses, rewritten to the file whenever they change
+pub struct Bans {
+    path: Option<PathBuf>,
+    bans: Vec<Ban>,
+}
+
+impl Bans {
+    pub fn new () -> Bans {
+        Bans { path: None, bans: Vec::new() }
+    }
+
+    pub fn open (path: &Path) -> io::Result<Bans> {
+        let mut bans = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(ban) = Ban::from_line(&line?) {
+                    bans.push(ban);
+                }
+            }
+        }
+        let now = now();
+        bans.retain(|ban| ban.is_active(now));
+        Ok(Bans { path: Some(path.to_path_buf()), bans })
+    }
+
+    pub fn find (&self, address: IpAddr) -> Option<&Ban> {
+        let now = now();
+        self.bans.iter().find(|ban| ban.address == address && ban.is_active(now))
+    }
+
+    // banning an address again replaces the earlier ban
+    pub fn add (&mut self, address: IpAddr, duration: Option<Duration>, reason: &str) -> Result<(), String> {
+        let now = now();
+        self.bans.retain(|ban| ban.address != address && ban.is_active(now));
+        self.bans.push(Ban {
+            address,
+            expires: duration.map(|duration| now.saturating_add(duration.as_secs().max(1))),
+            reason: reason.to_string(),
+        });
+        self.save()
+    }
+
+    pub fn remove (&mut self, address: IpAddr) -> Result<bool, String> {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.address != address);
+        if self.bans.len() == count {
+            return Ok(false);
+        }
+        self.save().map(|()| true)
+    }
+
+    fn save (&self) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let content: String = self.bans.iter().map(Ban::to_line).collect();
+        File::create(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("cannot save bans: {}", e))
+    }
+}
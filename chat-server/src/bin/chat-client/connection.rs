@@ -0,0 +1,59 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use ui::Input;
+
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// the writing half of the current connection, None while reconnecting
+#[derive(Clone)]
+pub struct Connection {
+    writer: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Connection {
+    pub fn send (&self, line: &str) -> bool {
+        match *self.writer.lock().unwrap() {
+            Some(ref mut stream) => stream.write_all(format!("{}\n", line).as_bytes()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+// connects to `addr` and reconnects whenever the connection drops,
+// passing every line from the server on to the interface
+pub fn spawn (addr: String, tx: Sender<Input>) -> Connection {
+    let connection = Connection { writer: Arc::new(Mutex::new(None)) };
+    let writer = connection.writer.clone();
+    thread::spawn(move || {
+        loop {
+            let update = match TcpStream::connect(&addr[..]).and_then(|s| Ok((s.try_clone()?, s))) {
+                Ok((reader, stream)) => {
+                    *writer.lock().unwrap() = Some(stream);
+                    if tx.send(Input::Connected).is_err() {
+                        return;
+                    }
+                    for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                        if tx.send(Input::Line(line)).is_err() {
+                            return;
+                        }
+                    }
+                    *writer.lock().unwrap() = None;
+                    Input::Disconnected("connection closed".to_string())
+                },
+                Err(e) => Input::Disconnected(e.to_string()),
+            };
+            if tx.send(update).is_err() {
+                return;
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+    connection
+}
@@ -0,0 +1,140 @@
+extern crate base64;
+
+use std::fs;
+use std::path::Path;
+
+use std::collections::HashMap;
+
+use self::base64::Engine;
+
+use connection::Connection;
+
+
+// well below what the server accepts in one line
+const CHUNK_SIZE: usize = 16 * 1024;
+
+// what became of a line from the server
+pub enum Seen {
+    // not about files, shown as it is
+    Other,
+    // part of a transfer, not shown
+    Quiet,
+    // shown in place of the line
+    Note(String),
+}
+
+struct Upload {
+    id: u64,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+struct Download {
+    name: String,
+    data: Vec<u8>,
+}
+
+// Files sent with `/send <nick> <path>` are read right away and uploaded one
+// chunk per tick once the server is ready for them, which keeps the client
+// within the server's flood limits. Files accepted with `/accept <id>` are
+// saved in the current directory.
+pub struct Files {
+    // read, waiting for the server to say it is ready for them
+    queued: Vec<(String, Vec<u8>)>,
+    uploads: Vec<Upload>,
+    downloads: HashMap<u64, Download>,
+}
+
+impl Files {
+    pub fn new () -> Files {
+        Files { queued: Vec::new(), uploads: Vec::new(), downloads: HashMap::new() }
+    }
+
+    // returns the command announcing the file to the server
+    pub fn send (&mut self, nick: &str, path: &str) -> Result<String, String> {
+        let data = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        // the server replaces spaces the same way
+        let name: String = Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+            .chars().map(|c| if c.is_whitespace() { '_' } else { c }).collect();
+        let command = format!("/send {} {} {}", nick, name, data.len());
+        self.queued.push((name, data));
+        Ok(command)
+    }
+
+    // transfers do not survive a reconnect
+    pub fn reset (&mut self) {
+        self.queued.clear();
+        self.uploads.clear();
+        self.downloads.clear();
+    }
+
+    pub fn tick (&mut self, connection: &Connection) {
+        for upload in self.uploads.iter_mut() {
+            let end = (upload.offset + CHUNK_SIZE).min(upload.data.len());
+            let data = base64::engine::general_purpose::STANDARD.encode(&upload.data[upload.offset..end]);
+            if connection.send(&format!("/chunk {} {} {}", upload.id, upload.offset, data)) {
+                upload.offset = end;
+            }
+        }
+        self.uploads.retain(|upload| upload.offset < upload.data.len());
+    }
+
+    pub fn receive (&mut self, line: &str) -> Seen {
+        let words: Vec<&str> = line.splitn(4, ' ').collect();
+        match words.as_slice() {
+            // upload 3 ready: send report.txt with /chunk ...
+            ["upload", id, "ready:", rest] => {
+                let name = rest.split(' ').nth(1).unwrap_or("");
+                match (id.parse(), self.queued.iter().position(|q| q.0 == name)) {
+                    (Ok(id), Some(index)) => {
+                        let (name, data) = self.queued.remove(index);
+                        self.uploads.push(Upload { id, data, offset: 0 });
+                        Seen::Note(format!("-- uploading {}", name))
+                    },
+                    _ => Seen::Other,
+                }
+            },
+            ["file", id, size, name] => match id.parse() {
+                Ok(id) => {
+                    self.downloads.insert(id, Download { name: name.to_string(), data: Vec::new() });
+                    Seen::Note(format!("-- receiving {} ({} bytes)", name, size))
+                },
+                Err(_) => Seen::Other,
+            },
+            ["chunk", id, _, data] => {
+                let download = id.parse().ok().and_then(|id: u64| self.downloads.get_mut(&id));
+                let data = base64::engine::general_purpose::STANDARD.decode(data);
+                match (download, data) {
+                    (Some(download), Ok(data)) => {
+                        download.data.extend(data);
+                        Seen::Quiet
+                    },
+                    _ => Seen::Other,
+                }
+            },
+            ["end", id] => match id.parse().ok().and_then(|id: u64| self.downloads.remove(&id)) {
+                Some(download) => Seen::Note(save(&download)),
+                None => Seen::Other,
+            },
+            _ => Seen::Other,
+        }
+    }
+}
+
+// never overwrites a file, report.txt becomes report.txt.1 and so on
+fn save (download: &Download) -> String {
+    let name = Path::new(&download.name).file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "download".to_string());
+    let mut path = name.clone();
+    let mut count = 0;
+    while Path::new(&path).exists() {
+        count += 1;
+        path = format!("{}.{}", name, count);
+    }
+    match fs::write(&path, &download.data) {
+        Ok(()) => format!("-- saved {}", path),
+        Err(e) => format!("-- cannot save {}: {}", path, e),
+    }
+}
@@ -0,0 +1,76 @@
+extern crate termion;
+
+mod connection;
+mod files;
+mod ui;
+
+use std::env;
+use std::io::stdin;
+use std::process;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use termion::input::TermRead;
+
+use ui::{App, Input};
+
+
+const USAGE: &str = "Usage: chat-client [--nick <nick>] [<addr>]
+
+Connects to a chat-server at <addr> (default 127.0.0.1:8080), reconnecting
+whenever the connection drops.
+
+Keys:
+    Enter                  send the message or /command
+    PageUp, PageDown       scroll the messages
+    /send <nick> <path>    offer a file, received files are saved in the current directory
+    Ctrl-C, /quit          leave";
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const TICK: Duration = Duration::from_millis(250);
+
+
+fn main () {
+    let mut addr = DEFAULT_ADDR.to_string();
+    let mut nick = env::var("USER").unwrap_or_default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            "-n" | "--nick" => match args.next() {
+                Some(value) => nick = value,
+                None => exit_usage("missing value for `--nick`"),
+            },
+            _ if arg.starts_with('-') => exit_usage(&format!("unexpected argument `{}`", arg)),
+            _ => addr = arg,
+        }
+    }
+
+    let (tx, rx) = channel();
+    let connection = connection::spawn(addr, tx.clone());
+
+    let keys = tx.clone();
+    thread::spawn(move || {
+        for key in stdin().keys().map_while(Result::ok) {
+            if keys.send(Input::Key(key)).is_err() {
+                return;
+            }
+        }
+    });
+    thread::spawn(move || {
+        while tx.send(Input::Tick).is_ok() {
+            thread::sleep(TICK);
+        }
+    });
+
+    App::new(connection, &nick).start(rx);
+}
+
+fn exit_usage (message: &str) -> ! {
+    eprintln!("Error: {}\n\n{}", message, USAGE);
+    process::exit(1);
+}
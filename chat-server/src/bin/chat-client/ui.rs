@@ -0,0 +1,284 @@
+extern crate termion;
+
+use std::io::{Write, stdout};
+use std::collections::BTreeSet;
+use std::sync::mpsc::Receiver;
+
+use self::termion::event::Key;
+use self::termion::raw::IntoRawMode;
+use self::termion::screen::AlternateScreen;
+use self::termion::cursor::Goto;
+use self::termion::style::{Invert, Reset};
+
+use connection::Connection;
+use files::{Files, Seen};
+
+
+const NAMES_WIDTH: u16 = 16;
+const MAX_MESSAGES: usize = 1000;
+const DEFAULT_ROOM: &str = "lobby";
+
+pub enum Input {
+    Key(Key),
+    Line(String),
+    Connected,
+    Disconnected(String),
+    // sent regularly so a resized terminal is redrawn
+    Tick,
+}
+
+
+pub struct App {
+    stdout: Box<dyn Write>,
+    connection: Connection,
+    files: Files,
+    // the nickname asked for on every connect, and the name the server knows this client by
+    nick: String,
+    me: Option<String>,
+    room: String,
+    names: BTreeSet<String>,
+    messages: Vec<String>,
+    // how many lines the message pane is scrolled back from the bottom
+    scroll: usize,
+    input: Vec<char>,
+    cursor: usize,
+    status: String,
+    size: (u16, u16),
+}
+
+impl App {
+    pub fn new (connection: Connection, nick: &str) -> App {
+        App {
+            stdout: Box::new(AlternateScreen::from(stdout().into_raw_mode().unwrap())),
+            connection,
+            files: Files::new(),
+            nick: nick.to_string(),
+            me: None,
+            room: DEFAULT_ROOM.to_string(),
+            names: BTreeSet::new(),
+            messages: Vec::new(),
+            scroll: 0,
+            input: Vec::new(),
+            cursor: 0,
+            status: "connecting".to_string(),
+            size: (0, 0),
+        }
+    }
+
+    pub fn start (&mut self, rx: Receiver<Input>) {
+        self.render();
+        for input in rx.iter() {
+            match input {
+                Input::Key(Key::Ctrl('c')) | Input::Key(Key::Ctrl('d')) => break,
+                Input::Key(key) => if !self.key(key) {
+                    break;
+                },
+                Input::Line(line) => self.receive(line),
+                Input::Connected => self.connected(),
+                Input::Disconnected(reason) => {
+                    self.me = None;
+                    self.names.clear();
+                    self.files.reset();
+                    self.status = format!("disconnected ({}), reconnecting", reason);
+                },
+                Input::Tick => {
+                    self.files.tick(&self.connection);
+                    if termion::terminal_size().ok() == Some(self.size) {
+                        continue;
+                    }
+                },
+            }
+            self.render();
+        }
+    }
+
+    // returns false once the user quits
+    fn key (&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('\n') => return self.submit(),
+            Key::Char(c) => {
+                self.input.insert(self.cursor, c);
+                self.cursor += 1;
+            },
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.input.remove(self.cursor);
+            },
+            Key::Delete if self.cursor < self.input.len() => { self.input.remove(self.cursor); },
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < self.input.len() => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.input.len(),
+            Key::PageUp => self.scroll += self.pane_size().1 as usize / 2,
+            Key::PageDown => self.scroll = self.scroll.saturating_sub(self.pane_size().1 as usize / 2),
+            _ => {},
+        }
+        true
+    }
+
+    fn submit (&mut self) -> bool {
+        let line: String = self.input.drain(..).collect();
+        self.cursor = 0;
+        self.scroll = 0;
+        if line == "/quit" {
+            return false;
+        }
+        if line.is_empty() {
+            return true;
+        }
+        // remembered so a reconnect asks for the same nickname again
+        if let Some(nick) = line.strip_prefix("/nick ") {
+            self.nick = nick.trim().to_string();
+        }
+        let line = match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["/send", nick, path] => match self.files.send(nick, path) {
+                Ok(command) => command,
+                Err(error) => {
+                    self.push(format!("-- {}", error));
+                    return true;
+                },
+            },
+            _ => line,
+        };
+        if !self.connection.send(&line) {
+            self.push("-- not connected, message not sent".to_string());
+        }
+        true
+    }
+
+    fn connected (&mut self) {
+        self.status = "connected".to_string();
+        let room = self.room.clone();
+        self.room = DEFAULT_ROOM.to_string();
+        if !self.nick.is_empty() {
+            self.connection.send(&format!("/nick {}", self.nick));
+        }
+        if room != DEFAULT_ROOM {
+            self.connection.send(&format!("/join {}", room));
+        }
+        self.connection.send("/names");
+    }
+
+    fn receive (&mut self, line: String) {
+        match self.files.receive(&line) {
+            Seen::Other => if self.track(&line) {
+                self.push(line);
+            },
+            Seen::Quiet => {},
+            Seen::Note(note) => self.push(note),
+        }
+    }
+
+    fn push (&mut self, line: String) {
+        self.messages.push(line);
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // follows who is in the room from the server's notices,
+    // returns whether the line should be shown
+    fn track (&mut self, line: &str) -> bool {
+        if let Some(rest) = line.strip_prefix("names in ") {
+            if let Some((room, names)) = rest.split_once(": ") {
+                if room == self.room {
+                    self.names = names.split_whitespace().map(String::from).collect();
+                }
+                return false;
+            }
+        }
+        // keepalives are answered quietly
+        if let Some(token) = line.strip_prefix("PING ") {
+            self.connection.send(&format!("/pong {}", token));
+            return false;
+        }
+        // chat messages start with `name:`, notices about people with a bare name
+        let words: Vec<&str> = line.split(' ').collect();
+        if words[0].ends_with(':') {
+            return true;
+        }
+        match words.as_slice() {
+            [name, "connected"] => {
+                // the server greets a client by announcing it to the room it has just entered,
+                // so the first arrival after connecting is this client under its own name
+                if self.me.is_none() {
+                    self.me = Some(name.to_string());
+                }
+                self.names.insert(name.to_string());
+            },
+            [name, "disconnected"] | [name, "timed", "out"] => { self.names.remove(*name); },
+            [name, "joined", room] if Some(*name) == self.me.as_deref() => {
+                self.room = room.to_string();
+                self.names.clear();
+                self.connection.send("/names");
+            },
+            [name, "joined", room] if *room == self.room => { self.names.insert(name.to_string()); },
+            [name, "left", room] if *room == self.room => { self.names.remove(*name); },
+            [old, "is", "now", "known", "as", new] => {
+                if self.names.remove(*old) {
+                    self.names.insert(new.to_string());
+                }
+                if Some(*old) == self.me.as_deref() {
+                    self.me = Some(new.to_string());
+                }
+            },
+            _ => {},
+        }
+        true
+    }
+
+    fn pane_size (&self) -> (u16, u16) {
+        let (width, height) = self.size;
+        (width.saturating_sub(NAMES_WIDTH + 1).max(1), height.saturating_sub(2).max(1))
+    }
+
+    fn render (&mut self) {
+        self.size = termion::terminal_size().unwrap_or((80, 24));
+        let (width, height) = self.size;
+        let (pane_width, pane_height) = self.pane_size();
+
+        // the message pane shows the wrapped messages ending `scroll` lines from the bottom
+        let lines: Vec<String> = self.messages.iter().flat_map(|m| wrap(m, pane_width as usize)).collect();
+        self.scroll = self.scroll.min(lines.len().saturating_sub(pane_height as usize));
+        let end = lines.len() - self.scroll;
+        let start = end.saturating_sub(pane_height as usize);
+        let names: Vec<&String> = self.names.iter().collect();
+
+        let mut screen = String::new();
+        for row in 0..pane_height as usize {
+            let line = lines.get(start + row).filter(|_| start + row < end).map(|l| l.as_str()).unwrap_or("");
+            let name = names.get(row).map(|n| n.as_str()).unwrap_or("");
+            screen.push_str(&format!("{}{}|{}", Goto(1, row as u16 + 1),
+                                     pad(line, pane_width as usize), pad(name, NAMES_WIDTH as usize)));
+        }
+
+        let status = format!(" #{}  {}  {}{}", self.room, self.me.as_deref().unwrap_or(&self.nick),
+                             self.status,
+                             if self.scroll > 0 { "  (scrolled back)" } else { "" });
+        screen.push_str(&format!("{}{}{}{}", Goto(1, height - 1), Invert, pad(&status, width as usize), Reset));
+
+        // the input line scrolls horizontally to keep the cursor visible
+        let visible = (width as usize).saturating_sub(3).max(1);
+        let offset = self.cursor.saturating_sub(visible);
+        let input: String = self.input.iter().skip(offset).take(visible).collect();
+        screen.push_str(&format!("{}> {}", Goto(1, height), pad(&input, visible + 1)));
+        screen.push_str(&format!("{}", Goto((self.cursor - offset) as u16 + 3, height)));
+
+        write!(self.stdout, "{}", screen).unwrap();
+        self.stdout.flush().unwrap();
+    }
+}
+
+// splits a message into lines of at most `width` characters
+fn wrap (message: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = message.chars().filter(|c| !c.is_control()).collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+}
+
+fn pad (text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    format!("{:width$}", text, width = width)
+}
@@ -0,0 +1,255 @@
+extern crate ring;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use std::collections::HashMap;
+
+use self::ring::rand::{SecureRandom, SystemRandom};
+
+use command::parse_duration;
+use event::Event;
+use plugin::{Action, Plugin};
+
+
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+const FILTER_MUTE: &str = "1m";
+// reminders further away than this are refused
+const MAX_REMINDER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// `!roll` or `!roll 3d6`, answered in the room
+pub struct Dice {
+    random: SystemRandom,
+}
+
+impl Dice {
+    pub fn new () -> Dice {
+        Dice { random: SystemRandom::new() }
+    }
+
+    fn roll (&self, sides: u32) -> u32 {
+        let mut bytes = [0; 4];
+        self.random.fill(&mut bytes).unwrap();
+        u32::from_le_bytes(bytes) % sides + 1
+    }
+}
+
+fn parse_dice (spec: &str) -> Option<(u32, u32)> {
+    let (count, sides) = spec.split_once('d')?;
+    let count = if count.is_empty() { 1 } else { count.parse().ok()? };
+    let sides = sides.parse().ok()?;
+    if (1..=MAX_DICE).contains(&count) && (1..=MAX_SIDES).contains(&sides) {
+        Some((count, sides))
+    } else {
+        None
+    }
+}
+
+impl Plugin for Dice {
+    fn name (&self) -> &str {
+        "dice"
+    }
+
+    fn on_event (&mut self, event: &Event) -> Vec<Action> {
+        let (room, name, text) = match *event {
+            Event::Message { ref room, ref name, ref text, .. } => (room, name, text),
+            _ => return Vec::new(),
+        };
+        let mut words = text.split_whitespace();
+        if words.next() != Some("!roll") {
+            return Vec::new();
+        }
+        let spec = words.next().unwrap_or("1d6");
+        let text = match parse_dice(spec) {
+            Some((count, sides)) => {
+                let rolls: Vec<u32> = (0..count).map(|_| self.roll(sides)).collect();
+                let total: u32 = rolls.iter().sum();
+                if count == 1 {
+                    format!("{} rolled {}: {}", name, spec, total)
+                } else {
+                    let rolls: Vec<String> = rolls.iter().map(u32::to_string).collect();
+                    format!("{} rolled {}: {} = {}", name, spec, rolls.join(" + "), total)
+                }
+            },
+            None => format!("{}: try !roll 2d6, with at most {} dice of {} sides",
+                            name, MAX_DICE, MAX_SIDES),
+        };
+        vec![Action::Say { room: room.clone(), text }]
+    }
+}
+
+
+struct Reminder {
+    due: Instant,
+    // None for reminders asked for in a private message
+    room: Option<String>,
+    name: String,
+    text: String,
+}
+
+impl Reminder {
+    fn reply (&self, text: String) -> Action {
+        match self.room {
+            Some(ref room) => Action::Say { room: room.clone(), text: format!("{}: {}", self.name, text) },
+            None => Action::Tell { to: self.name.clone(), text },
+        }
+    }
+}
+
+// `!remind 10m stretch your legs`, repeated in the room when the time is up,
+// or privately when asked in a private message
+pub struct Reminders {
+    pending: Vec<Reminder>,
+}
+
+impl Reminders {
+    pub fn new () -> Reminders {
+        Reminders { pending: Vec::new() }
+    }
+}
+
+impl Plugin for Reminders {
+    fn name (&self) -> &str {
+        "reminders"
+    }
+
+    fn on_event (&mut self, event: &Event) -> Vec<Action> {
+        let (room, name, text) = match *event {
+            Event::Message { ref room, ref name, ref text, .. } => (Some(room.clone()), name, text),
+            Event::Private { ref from, ref to, ref text, .. } if to == self.name() => (None, from, text),
+            _ => return Vec::new(),
+        };
+        let mut words = text.splitn(3, ' ');
+        if words.next() != Some("!remind") {
+            return Vec::new();
+        }
+        let mut reminder = Reminder { due: Instant::now(), room, name: name.clone(), text: String::new() };
+        let reply = match (words.next().and_then(parse_duration), words.next()) {
+            (Some(delay), Some(text)) if !text.trim().is_empty() => {
+                reminder.due = match reminder.due.checked_add(delay) {
+                    Some(due) if delay <= MAX_REMINDER => due,
+                    _ => {
+                        let text = format!("reminder too far in the future, at most {}",
+                                           describe(MAX_REMINDER));
+                        return vec![reminder.reply(text)];
+                    },
+                };
+                reminder.text = text.trim().to_string();
+                let reply = reminder.reply(format!("I will remind you in {}", describe(delay)));
+                self.pending.push(reminder);
+                reply
+            },
+            _ => reminder.reply("try !remind 10m stretch your legs".to_string()),
+        };
+        vec![reply]
+    }
+
+    fn on_tick (&mut self) -> Vec<Action> {
+        let now = Instant::now();
+        let (due, pending): (Vec<Reminder>, Vec<Reminder>) =
+            self.pending.drain(..).partition(|r| r.due <= now);
+        self.pending = pending;
+        due.iter().map(|reminder| reminder.reply(format!("reminder: {}", reminder.text))).collect()
+    }
+}
+
+fn describe (duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let units = [(86400, "d"), (3600, "h"), (60, "m")];
+    match units.iter().find(|unit| seconds >= unit.0 && seconds.is_multiple_of(unit.0)) {
+        Some(&(size, unit)) => format!("{}{}", seconds / size, unit),
+        None => format!("{}s", seconds),
+    }
+}
+
+
+// Titles of links, looked up in a local file of `url<TAB>title` lines so the
+// server never fetches anything on behalf of its users.
+pub struct LinkPreviews {
+    titles: HashMap<String, String>,
+}
+
+impl LinkPreviews {
+    pub fn open (path: &Path) -> Result<LinkPreviews, String> {
+        let read = || -> io::Result<HashMap<String, String>> {
+            let mut titles = HashMap::new();
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if let Some((url, title)) = line.split_once('\t') {
+                    titles.insert(url.trim().to_string(), title.trim().to_string());
+                }
+            }
+            Ok(titles)
+        };
+        read().map(|titles| LinkPreviews { titles })
+            .map_err(|e| format!("cannot read link cache {}: {}", path.display(), e))
+    }
+}
+
+impl Plugin for LinkPreviews {
+    fn name (&self) -> &str {
+        "links"
+    }
+
+    fn on_event (&mut self, event: &Event) -> Vec<Action> {
+        let (room, text) = match *event {
+            Event::Message { ref room, ref text, .. } => (room, text),
+            _ => return Vec::new(),
+        };
+        text.split_whitespace()
+            .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+            .filter_map(|url| self.titles.get(url))
+            .map(|title| Action::Say { room: room.clone(), text: format!("[link] {}", title) })
+            .collect()
+    }
+}
+
+
+// mutes anyone using one of the words listed in a file, one per line
+pub struct WordFilter {
+    words: Vec<String>,
+}
+
+impl WordFilter {
+    pub fn open (path: &Path) -> Result<WordFilter, String> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|e| format!("cannot read filter words {}: {}", path.display(), e))?;
+        let words = content.lines()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(WordFilter { words })
+    }
+}
+
+impl Plugin for WordFilter {
+    fn name (&self) -> &str {
+        "filter"
+    }
+
+    fn on_event (&mut self, event: &Event) -> Vec<Action> {
+        let (room, name, text) = match *event {
+            Event::Message { ref room, ref name, ref text, .. } => (room, name, text.to_lowercase()),
+            _ => return Vec::new(),
+        };
+        let offending = text.split(|c: char| !c.is_alphanumeric())
+            .any(|word| self.words.iter().any(|w| w == word));
+        if !offending {
+            return Vec::new();
+        }
+        vec![
+            Action::Command(format!("/mute {} {}", name, FILTER_MUTE)),
+            Action::Say {
+                room: room.clone(),
+                text: format!("{} is muted for {}, mind your language", name, FILTER_MUTE),
+            },
+        ]
+    }
+}
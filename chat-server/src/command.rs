@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use server::normalize_room;
+
+
+pub enum Command {
+    Join(String),
+    History(usize),
+    Names,
+    Pong,
+    // switches the connection between plain text and JSON lines
+    Json(bool),
+    // only from JSON clients, whose chat lines do not go through `parse`
+    Say(String),
+    Nick(String),
+    Msg(String, String),
+    Register(String),
+    Login(String, String),
+    // the mentions kept for the logged in nickname
+    Inbox,
+    ClearInbox,
+    Kick(String, String),
+    Ban(String, Option<Duration>, String),
+    Unban(String),
+    Mute(String, Option<Duration>),
+    Unmute(String),
+    Op(String),
+    Stats,
+    // a file of `size` bytes for a nickname, uploaded with Chunk
+    Send(String, String, u64),
+    Chunk(u64, u64, String),
+    Accept(u64),
+    Decline(u64),
+    Download(u64),
+    // an empty reason is shown as plain "away"
+    Away(String),
+    Back,
+    Who(Option<String>),
+}
+
+const DEFAULT_HISTORY_COUNT: usize = 10;
+
+// lines starting with a slash are commands, everything else is chat
+pub fn parse (line: &str) -> Option<Result<Command, String>> {
+    if !line.starts_with('/') {
+        return None;
+    }
+    let mut words = line[1..].split_whitespace();
+    let name = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+
+    let command = match (name, args.as_slice()) {
+        ("join", [room]) => normalize_room(room)
+            .map(Command::Join)
+            .ok_or(format!("invalid room name {}", room)),
+        ("join", _) => Err("usage: /join <room>".to_string()),
+        ("history", []) => Ok(Command::History(DEFAULT_HISTORY_COUNT)),
+        ("history", [count]) => count.parse()
+            .map(Command::History)
+            .map_err(|_| "usage: /history [count]".to_string()),
+        ("history", _) => Err("usage: /history [count]".to_string()),
+        // the answer to a keepalive PING, whatever token it carries
+        ("pong", _) => Ok(Command::Pong),
+        ("protocol", ["json"]) => Ok(Command::Json(true)),
+        ("protocol", ["text"]) => Ok(Command::Json(false)),
+        ("protocol", _) => Err("usage: /protocol <text|json>".to_string()),
+        ("names", []) => Ok(Command::Names),
+        ("names", _) => Err("usage: /names".to_string()),
+        ("nick", [nick]) => Ok(Command::Nick(nick.to_string())),
+        ("nick", _) => Err("usage: /nick <nickname>".to_string()),
+        ("msg", [to, _, ..]) => Ok(Command::Msg(to.to_string(), rest(line, 2))),
+        ("msg", _) => Err("usage: /msg <nickname> <message>".to_string()),
+        ("register", [password]) => Ok(Command::Register(password.to_string())),
+        ("register", _) => Err("usage: /register <password>".to_string()),
+        ("login", [nick, password]) => Ok(Command::Login(nick.to_string(), password.to_string())),
+        ("login", _) => Err("usage: /login <nickname> <password>".to_string()),
+        ("inbox", []) => Ok(Command::Inbox),
+        ("inbox", ["clear"]) => Ok(Command::ClearInbox),
+        ("inbox", _) => Err("usage: /inbox [clear]".to_string()),
+        ("kick", [nick, ..]) => Ok(Command::Kick(nick.to_string(), rest(line, 2))),
+        ("kick", _) => Err("usage: /kick <nickname> [reason]".to_string()),
+        ("ban", [target, duration, ..]) => match parse_duration(duration) {
+            Some(duration) => Ok(Command::Ban(target.to_string(), Some(duration), rest(line, 3))),
+            None => Ok(Command::Ban(target.to_string(), None, rest(line, 2))),
+        },
+        ("ban", [target]) => Ok(Command::Ban(target.to_string(), None, String::new())),
+        ("ban", _) => Err("usage: /ban <nickname|address> [duration] [reason]".to_string()),
+        ("unban", [address]) => Ok(Command::Unban(address.to_string())),
+        ("unban", _) => Err("usage: /unban <address>".to_string()),
+        ("mute", [nick]) => Ok(Command::Mute(nick.to_string(), None)),
+        ("mute", [nick, duration]) => match parse_duration(duration) {
+            Some(duration) => Ok(Command::Mute(nick.to_string(), Some(duration))),
+            None => Err(format!("invalid duration {}, e.g. 30s, 10m, 2h or 1d", duration)),
+        },
+        ("mute", _) => Err("usage: /mute <nickname> [duration]".to_string()),
+        ("unmute", [nick]) => Ok(Command::Unmute(nick.to_string())),
+        ("unmute", _) => Err("usage: /unmute <nickname>".to_string()),
+        ("op", [nick]) => Ok(Command::Op(nick.to_string())),
+        ("op", _) => Err("usage: /op <nickname>".to_string()),
+        ("away", _) => Ok(Command::Away(rest(line, 1))),
+        ("back", []) => Ok(Command::Back),
+        ("back", _) => Err("usage: /back".to_string()),
+        ("who", []) => Ok(Command::Who(None)),
+        ("who", [room]) => normalize_room(room)
+            .map(|room| Command::Who(Some(room)))
+            .ok_or(format!("invalid room name {}", room)),
+        ("who", _) => Err("usage: /who [room]".to_string()),
+        ("stats", []) => Ok(Command::Stats),
+        ("stats", _) => Err("usage: /stats".to_string()),
+        ("send", [nick, file, size]) => match size.parse() {
+            Ok(size) => Ok(Command::Send(nick.to_string(), file.to_string(), size)),
+            Err(_) => Err(format!("invalid file size {}", size)),
+        },
+        ("send", _) => Err("usage: /send <nickname> <file> <size>".to_string()),
+        ("chunk", [id, offset, data]) => match (id.parse(), offset.parse()) {
+            (Ok(id), Ok(offset)) => Ok(Command::Chunk(id, offset, data.to_string())),
+            _ => Err("usage: /chunk <id> <offset> <base64>".to_string()),
+        },
+        ("chunk", _) => Err("usage: /chunk <id> <offset> <base64>".to_string()),
+        ("accept", [id]) => id.parse().map(Command::Accept).map_err(|_| "usage: /accept <id>".to_string()),
+        ("accept", _) => Err("usage: /accept <id>".to_string()),
+        ("decline", [id]) => id.parse().map(Command::Decline).map_err(|_| "usage: /decline <id>".to_string()),
+        ("decline", _) => Err("usage: /decline <id>".to_string()),
+        ("download", [id]) => id.parse().map(Command::Download).map_err(|_| "usage: /download <id>".to_string()),
+        ("download", _) => Err("usage: /download <id>".to_string()),
+        _ => Err(format!("unknown command /{}", name)),
+    };
+    Some(command)
+}
+
+// the text after the first `count` words of a line, with its spacing intact
+fn rest (line: &str, count: usize) -> String {
+    let mut rest = line;
+    for _ in 0..count {
+        rest = rest.trim_start();
+        rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+    }
+    rest.trim_start().to_string()
+}
+
+// a number followed by s, m, h or d, e.g. `10m`
+pub fn parse_duration (text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let count: u64 = text[..split].parse().ok()?;
+    let unit = match &text[split..] {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    count.checked_mul(unit).map(Duration::from_secs)
+}
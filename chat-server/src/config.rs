@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use link::is_valid_server_name;
+use log::Level;
+use ratelimit::FloodLimits;
+
+
+pub const USAGE: &str = "Usage: chat-server [options]
+
+Options:
+    -l, --listen <addr>    accept TCP connections on <addr>, e.g. 0.0.0.0:8080
+                           or [::1]:8080 (may be given more than once)
+    -u, --unix <path>      accept connections on a Unix domain socket
+    --tls-listen <addr>    accept TLS connections on <addr> (may be given more than once)
+    --tls-cert <file>      PEM certificate chain for the TLS listeners
+    --tls-key <file>       PEM private key for the TLS listeners
+    --irc-listen <addr>    accept IRC clients on <addr> (may be given more than once)
+    --ws-listen <addr>     accept WebSocket clients on <addr> (may be given more than once)
+    --history-size <n>     number of recent messages kept per room (default 50)
+    --history-file <file>  append every message to <file> and reload it on startup
+    --accounts-file <file> store registered nicknames and password hashes in <file>,
+                           registration is disabled without it
+    --require-auth <bool>  only broadcast messages of clients who have logged in
+    --operator <nick>      give operator rights to the registered nickname <nick>
+                           once logged in (may be given more than once); <nick> must
+                           already be registered, so nobody else can claim it first
+    --bans-file <file>     keep banned addresses in <file> across restarts
+    --inbox-file <file>    keep messages for registered nicknames which are offline, and
+                           the messages mentioning them, in <file> across restarts
+    --ping-interval <secs> ping clients which have been quiet this long (default 0, never)
+    --idle-timeout <secs>  disconnect clients which have been quiet this long (default 0,
+                           never); any line counts as an answer to a ping, so this also
+                           disconnects people who are merely idle
+    --plugin <name>        run a built-in plugin: dice (`!roll 2d6`), reminders
+                           (`!remind 10m tea`), links or filter (may be given more than once)
+    --link-cache <file>    titles for the links plugin, one `url<TAB>title` per line
+    --filter-words <file>  words the filter plugin mutes people for, one per line
+    --max-clients <n>      refuse connections beyond <n> clients (default 1000, 0 for no limit)
+    --max-clients-per-ip <n>
+                           refuse connections beyond <n> per address (default 20, 0 for no limit)
+    --flood-messages <n>   lines a client may send per second (default 5, 0 for no limit)
+    --flood-bytes <n>      bytes a client may send per second (default 2048, 0 for no limit)
+    --flood-burst <n>      seconds worth of input a client may send at once (default 2)
+    --flood-warnings <n>   warnings given to a flooding client before it is
+                           disconnected (default 3)
+    --metrics-listen <addr>
+                           serve counters in the Prometheus text format at
+                           http://<addr>/metrics, best kept to a local address
+    --log-level <level>    error, warn, info or debug (default info)
+    --hide-addresses <bool>
+                           leave client addresses out of the log and transcripts
+    --transcript-dir <dir> write what is said in every room to <dir>/<room>/<date>.log
+    --spool-dir <dir>      keep files sent with /send in <dir> until they are fetched,
+                           file transfers are disabled without it
+    --max-file-size <n>    largest file in bytes which may be sent (default 1048576)
+    --server-name <name>   how this server is known to linked servers (default chat-server),
+                           every server in a network needs a name of its own
+    --link-listen <addr>   accept links from other servers on <addr> (may be given more than once),
+                           linked servers are trusted completely, so keep this to private networks
+    --link <addr>          link to the server accepting links on <addr>, and again whenever
+                           the link is lost (may be given more than once)
+    -c, --config <file>    read options from <file>, one `key = value` per line
+    -h, --help             print this message
+
+Every long option can also be written in the config file, e.g. `listen = [::]:8080`.
+Options given on the command line take precedence over the config file.";
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+const DEFAULT_HISTORY_SIZE: usize = 50;
+const DEFAULT_PING_INTERVAL: u64 = 0;
+const DEFAULT_IDLE_TIMEOUT: u64 = 0;
+const DEFAULT_MAX_CLIENTS: usize = 1000;
+const DEFAULT_MAX_CLIENTS_PER_IP: usize = 20;
+const DEFAULT_MAX_FILE_SIZE: usize = 1024 * 1024;
+const DEFAULT_SERVER_NAME: &str = "chat-server";
+const DEFAULT_FLOOD_LIMITS: FloodLimits = FloodLimits {
+    messages: 5.0,
+    bytes: 2048.0,
+    burst: 2.0,
+    warnings: 3,
+};
+
+// options which may appear more than once and accumulate into a list
+const LIST_KEYS: [&str; 8] = ["listen", "tls-listen", "irc-listen", "ws-listen", "operator", "plugin",
+                              "link-listen", "link"];
+
+
+pub struct Config {
+    pub listen: Vec<SocketAddr>,
+    pub unix: Option<PathBuf>,
+    pub tls_listen: Vec<SocketAddr>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub irc_listen: Vec<SocketAddr>,
+    pub ws_listen: Vec<SocketAddr>,
+    pub history_size: usize,
+    pub history_file: Option<PathBuf>,
+    pub accounts_file: Option<PathBuf>,
+    pub require_auth: bool,
+    pub operators: Vec<String>,
+    pub bans_file: Option<PathBuf>,
+    pub inbox_file: Option<PathBuf>,
+    pub plugins: Vec<String>,
+    pub link_cache: Option<PathBuf>,
+    pub filter_words: Option<PathBuf>,
+    pub ping_interval: Duration,
+    pub idle_timeout: Duration,
+    pub max_clients: usize,
+    pub max_clients_per_ip: usize,
+    pub flood: FloodLimits,
+    pub metrics_listen: Option<SocketAddr>,
+    pub log_level: Level,
+    pub hide_addresses: bool,
+    pub transcript_dir: Option<PathBuf>,
+    pub spool_dir: Option<PathBuf>,
+    pub max_file_size: usize,
+    pub server_name: String,
+    pub link_listen: Vec<SocketAddr>,
+    pub link: Vec<SocketAddr>,
+}
+
+impl Config {
+    fn new () -> Config {
+        Config {
+            listen: Vec::new(),
+            unix: None,
+            tls_listen: Vec::new(),
+            tls_cert: None,
+            tls_key: None,
+            irc_listen: Vec::new(),
+            ws_listen: Vec::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            history_file: None,
+            accounts_file: None,
+            require_auth: false,
+            operators: Vec::new(),
+            bans_file: None,
+            inbox_file: None,
+            plugins: Vec::new(),
+            link_cache: None,
+            filter_words: None,
+            ping_interval: Duration::from_secs(DEFAULT_PING_INTERVAL),
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            max_clients_per_ip: DEFAULT_MAX_CLIENTS_PER_IP,
+            flood: DEFAULT_FLOOD_LIMITS,
+            metrics_listen: None,
+            log_level: Level::Info,
+            hide_addresses: false,
+            transcript_dir: None,
+            spool_dir: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            server_name: DEFAULT_SERVER_NAME.to_string(),
+            link_listen: Vec::new(),
+            link: Vec::new(),
+        }
+    }
+
+    pub fn from_args<I: Iterator<Item=String>> (args: I) -> Result<Config, String> {
+        let options = parse_args(args)?;
+        let mut config = Config::new();
+
+        for (_, path) in options.iter().filter(|o| o.0 == "config") {
+            for (key, value) in read_config_file(path)? {
+                config.apply(&key, &value).map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+
+        // list options from the command line replace those from the file
+        for key in LIST_KEYS.iter() {
+            if options.iter().any(|o| o.0 == *key) {
+                config.clear(key);
+            }
+        }
+        for (key, value) in options.into_iter().filter(|o| o.0 != "config") {
+            config.apply(&key, &value).map_err(|e| format!("--{}: {}", key, e))?;
+        }
+
+        if config.listen.is_empty() && config.unix.is_none() && config.tls_listen.is_empty() &&
+           config.irc_listen.is_empty() && config.ws_listen.is_empty() {
+            config.listen.push(DEFAULT_LISTEN.parse().unwrap());
+        }
+        if !config.tls_listen.is_empty() && (config.tls_cert.is_none() || config.tls_key.is_none()) {
+            return Err("--tls-listen needs both --tls-cert and --tls-key".to_string());
+        }
+        if config.tls_listen.is_empty() && (config.tls_cert.is_some() || config.tls_key.is_some()) {
+            return Err("--tls-cert and --tls-key need a --tls-listen".to_string());
+        }
+        if config.require_auth && config.accounts_file.is_none() {
+            return Err("--require-auth needs an --accounts-file".to_string());
+        }
+        if !config.operators.is_empty() && config.accounts_file.is_none() {
+            return Err("--operator needs an --accounts-file".to_string());
+        }
+        if config.inbox_file.is_some() && config.accounts_file.is_none() {
+            return Err("--inbox-file needs an --accounts-file".to_string());
+        }
+        Ok(config)
+    }
+
+    fn apply (&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "listen" => self.listen.push(parse_addr(value)?),
+            "unix" => self.unix = Some(PathBuf::from(value)),
+            "tls-listen" => self.tls_listen.push(parse_addr(value)?),
+            "tls-cert" => self.tls_cert = Some(PathBuf::from(value)),
+            "tls-key" => self.tls_key = Some(PathBuf::from(value)),
+            "irc-listen" => self.irc_listen.push(parse_addr(value)?),
+            "ws-listen" => self.ws_listen.push(parse_addr(value)?),
+            "history-size" => self.history_size = parse_number(value)?,
+            "history-file" => self.history_file = Some(PathBuf::from(value)),
+            "accounts-file" => self.accounts_file = Some(PathBuf::from(value)),
+            "require-auth" => self.require_auth = parse_bool(value)?,
+            "operator" => self.operators.push(value.to_string()),
+            "bans-file" => self.bans_file = Some(PathBuf::from(value)),
+            "inbox-file" => self.inbox_file = Some(PathBuf::from(value)),
+            "plugin" => self.plugins.push(value.to_string()),
+            "link-cache" => self.link_cache = Some(PathBuf::from(value)),
+            "filter-words" => self.filter_words = Some(PathBuf::from(value)),
+            "ping-interval" => self.ping_interval = Duration::from_secs(parse_number(value)? as u64),
+            "idle-timeout" => self.idle_timeout = Duration::from_secs(parse_number(value)? as u64),
+            "max-clients" => self.max_clients = parse_number(value)?,
+            "max-clients-per-ip" => self.max_clients_per_ip = parse_number(value)?,
+            "flood-messages" => self.flood.messages = parse_number(value)? as f64,
+            "flood-bytes" => self.flood.bytes = parse_number(value)? as f64,
+            "flood-burst" => self.flood.burst = parse_number(value)? as f64,
+            "flood-warnings" => self.flood.warnings = parse_number(value)? as u32,
+            "metrics-listen" => self.metrics_listen = Some(parse_addr(value)?),
+            "log-level" => {
+                self.log_level = Level::parse(value).ok_or(format!("invalid log level `{}`", value))?
+            },
+            "hide-addresses" => self.hide_addresses = parse_bool(value)?,
+            "transcript-dir" => self.transcript_dir = Some(PathBuf::from(value)),
+            "spool-dir" => self.spool_dir = Some(PathBuf::from(value)),
+            "max-file-size" => self.max_file_size = parse_number(value)?,
+            "server-name" if is_valid_server_name(value) => self.server_name = value.to_string(),
+            "server-name" => return Err(format!("invalid server name `{}`", value)),
+            "link-listen" => self.link_listen.push(parse_addr(value)?),
+            "link" => self.link.push(parse_addr(value)?),
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+        Ok(())
+    }
+
+    fn clear (&mut self, key: &str) {
+        match key {
+            "listen" => self.listen.clear(),
+            "tls-listen" => self.tls_listen.clear(),
+            "irc-listen" => self.irc_listen.clear(),
+            "ws-listen" => self.ws_listen.clear(),
+            "operator" => self.operators.clear(),
+            "plugin" => self.plugins.clear(),
+            "link-listen" => self.link_listen.clear(),
+            "link" => self.link.clear(),
+            _ => {},
+        }
+    }
+}
+
+fn parse_addr (value: &str) -> Result<SocketAddr, String> {
+    value.parse().map_err(|_| format!("invalid listen address `{}`", value))
+}
+
+fn parse_number (value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("expected a number, found `{}`", value))
+}
+
+fn parse_bool (value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected true or false, found `{}`", value)),
+    }
+}
+
+fn parse_args<I: Iterator<Item=String>> (mut args: I) -> Result<Vec<(String, String)>, String> {
+    let mut options = Vec::new();
+    while let Some(arg) = args.next() {
+        let key = match arg.as_str() {
+            "-l" => "listen".to_string(),
+            "-u" => "unix".to_string(),
+            "-c" => "config".to_string(),
+            _ if arg.starts_with("--") => arg[2..].to_string(),
+            _ => return Err(format!("unexpected argument `{}`\n\n{}", arg, USAGE)),
+        };
+        match args.next() {
+            Some(value) => options.push((key, value)),
+            None => return Err(format!("missing value for `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_config_file (path: &str) -> Result<Vec<(String, String)>, String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("cannot read config file {}: {}", path, e))?;
+
+    let mut options = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.find('=') {
+            Some(i) => options.push((line[..i].trim().to_string(), line[i + 1..].trim().to_string())),
+            None => return Err(format!("{}:{}: expected `key = value`", path, number + 1)),
+        }
+    }
+    Ok(options)
+}
@@ -0,0 +1,60 @@
+// `id` is the connection id of the client the event comes from
+#[derive(Clone)]
+pub enum Event {
+    Connected { id: usize, room: String, name: String },
+    // `reason` is "disconnected" or "timed out"
+    Disconnected { id: usize, rooms: Vec<String>, name: String, reason: String },
+    Joined { id: usize, room: String, name: String },
+    Left { id: usize, room: String, name: String },
+    Message { id: usize, room: String, name: String, text: String },
+    Private { id: usize, from: String, to: String, text: String },
+    Nick { id: usize, rooms: Vec<String>, old: String, new: String },
+    // the last event, every stream is closed right after it
+    Shutdown,
+}
+
+pub const SHUTDOWN_NOTICE: &str = "the server is shutting down, please reconnect in a moment";
+
+impl Event {
+    pub fn sender (&self) -> Option<usize> {
+        match *self {
+            Event::Connected { id, .. } | Event::Disconnected { id, .. } |
+            Event::Joined { id, .. } | Event::Left { id, .. } |
+            Event::Message { id, .. } | Event::Private { id, .. } |
+            Event::Nick { id, .. } => Some(id),
+            Event::Shutdown => None,
+        }
+    }
+
+    // whether a client called `name`, sitting in `rooms`, should see this event
+    pub fn is_for (&self, name: &str, rooms: &[String]) -> bool {
+        match *self {
+            Event::Connected { ref room, .. } |
+            Event::Joined { ref room, .. } |
+            Event::Message { ref room, .. } => rooms.contains(room),
+            // the leaving client is no longer in the room but still sees its own departure
+            Event::Left { ref room, name: ref leaver, .. } => name == leaver || rooms.contains(room),
+            Event::Disconnected { rooms: ref event_rooms, .. } => {
+                event_rooms.iter().any(|room| rooms.contains(room))
+            },
+            Event::Private { ref from, ref to, .. } => name == from || name == to,
+            Event::Nick { rooms: ref event_rooms, ref new, .. } => {
+                name == new || event_rooms.iter().any(|room| rooms.contains(room))
+            },
+            Event::Shutdown => true,
+        }
+    }
+
+    pub fn to_text (&self) -> String {
+        match *self {
+            Event::Connected { ref name, .. } => format!("{} connected\n", name),
+            Event::Disconnected { ref name, ref reason, .. } => format!("{} {}\n", name, reason),
+            Event::Joined { ref room, ref name, .. } => format!("{} joined {}\n", name, room),
+            Event::Left { ref room, ref name, .. } => format!("{} left {}\n", name, room),
+            Event::Message { ref name, ref text, .. } => format!("{}: {}\n", name, text),
+            Event::Private { ref from, ref to, ref text, .. } => format!("[{} -> {}] {}\n", from, to, text),
+            Event::Nick { ref old, ref new, .. } => format!("{} is now known as {}\n", old, new),
+            Event::Shutdown => format!("{}\n", SHUTDOWN_NOTICE),
+        }
+    }
+}
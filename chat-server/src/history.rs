@@ -0,0 +1,133 @@
+extern crate time;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+use std::collections::{HashMap, VecDeque};
+
+
+
+#[derive(Clone)]
+pub struct Entry {
+    pub time: i64,
+    pub room: String,
+    pub name: String,
+    pub text: String,
+}
+
+impl Entry {
+    pub fn new (room: &str, name: &str, text: &str) -> Entry {
+        Entry {
+            time: time::get_time().sec,
+            room: room.to_string(),
+            name: name.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn clock (&self) -> String {
+        let timestamp = time::at(time::Timespec::new(self.time, 0));
+        timestamp.strftime("%H:%M").unwrap().to_string()
+    }
+
+    pub fn to_text (&self) -> String {
+        format!("[{}] {}: {}\n", self.clock(), self.name, self.text)
+    }
+
+    // one entry per line: time, room and name separated by tabs, followed by the text
+    fn to_line (&self) -> String {
+        format!("{}\t{}\t{}\t{}\n", self.time, self.room, self.name, self.text)
+    }
+
+    fn from_line (line: &str) -> Option<Entry> {
+        let mut fields = line.splitn(4, '\t');
+        let time = fields.next().and_then(|t| t.parse().ok());
+        match (time, fields.next(), fields.next(), fields.next()) {
+            (Some(time), Some(room), Some(name), Some(text)) => Some(Entry {
+                time,
+                room: room.to_string(),
+                name: name.to_string(),
+                text: text.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+
+// a bounded buffer of the most recent messages of every room,
+// optionally mirrored to an append-only file
+pub struct History {
+    capacity: usize,
+    rooms: HashMap<String, VecDeque<Entry>>,
+    file: Option<File>,
+}
+
+impl History {
+    pub fn new (capacity: usize) -> History {
+        History {
+            capacity,
+            rooms: HashMap::new(),
+            file: None,
+        }
+    }
+
+    pub fn open (capacity: usize, path: &Path) -> io::Result<History> {
+        let mut history = History::new(capacity);
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(entry) = Entry::from_line(&line?) {
+                    history.remember(entry);
+                }
+            }
+        }
+        history.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(history)
+    }
+
+    pub fn push (&mut self, entry: Entry) {
+        let mut failed = false;
+        if let Some(ref mut file) = self.file {
+            failed = file.write_all(entry.to_line().as_bytes()).is_err();
+        }
+        if failed {
+            log!(Error, "cannot write history file, history will no longer be saved");
+            self.file = None;
+        }
+        self.remember(entry);
+    }
+
+    // makes sure everything written so far is on disk
+    pub fn sync (&self) -> io::Result<()> {
+        match self.file {
+            Some(ref file) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn recent (&self, room: &str, count: usize) -> Vec<Entry> {
+        match self.rooms.get(room) {
+            Some(entries) => {
+                let skip = entries.len().saturating_sub(count);
+                entries.iter().skip(skip).cloned().collect()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    pub fn capacity (&self) -> usize {
+        self.capacity
+    }
+
+    fn remember (&mut self, entry: Entry) {
+        let capacity = self.capacity;
+        let entries = self.rooms.entry(entry.room.clone()).or_default();
+        entries.push_back(entry);
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+}
@@ -0,0 +1,151 @@
+extern crate time;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+
+// the oldest mail of a nickname is dropped beyond this
+const MAX_MAIL: usize = 50;
+
+// a private message to a registered nickname which was not connected,
+// or a room message mentioning it with @nick
+#[derive(Clone)]
+pub struct Mail {
+    pub to: String,
+    // None for a private message
+    pub room: Option<String>,
+    pub time: i64,
+    pub from: String,
+    pub text: String,
+}
+
+impl Mail {
+    pub fn new (to: &str, room: Option<&str>, from: &str, text: &str) -> Mail {
+        Mail {
+            to: to.to_string(),
+            room: room.map(str::to_string),
+            time: time::get_time().sec,
+            from: from.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn to_text (&self) -> String {
+        let timestamp = time::at(time::Timespec::new(self.time, 0));
+        let date = timestamp.strftime("%b %d %H:%M").unwrap();
+        match self.room {
+            Some(ref room) => format!("[{}] {} in {}: {}", date, self.from, room, self.text),
+            None => format!("[{}] [{} -> {}] {}", date, self.from, self.to, self.text),
+        }
+    }
+
+    // recipient, room (empty for a private message), time and sender separated by tabs,
+    // followed by the text
+    fn to_line (&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\n", self.to, self.room.as_deref().unwrap_or(""),
+                self.time, self.from, self.text)
+    }
+
+    fn from_line (line: &str) -> Option<Mail> {
+        let mut fields = line.splitn(5, '\t');
+        let to = fields.next()?.to_string();
+        let room = match fields.next()? {
+            "" => None,
+            room => Some(room.to_string()),
+        };
+        let time = fields.next()?.parse().ok()?;
+        let from = fields.next()?.to_string();
+        let text = fields.next()?.to_string();
+        Some(Mail { to, room, time, from, text })
+    }
+}
+
+
+// mail for registered nicknames, rewritten to the file whenever it changes
+// and kept in memory only without one
+pub struct Inbox {
+    path: Option<PathBuf>,
+    mail: Vec<Mail>,
+}
+
+impl Inbox {
+    pub fn new () -> Inbox {
+        Inbox { path: None, mail: Vec::new() }
+    }
+
+    pub fn open (path: &Path) -> io::Result<Inbox> {
+        let mut mail = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(entry) = Mail::from_line(&line?) {
+                    mail.push(entry);
+                }
+            }
+        }
+        Ok(Inbox { path: Some(path.to_path_buf()), mail })
+    }
+
+    pub fn add (&mut self, mail: Mail) -> Result<(), String> {
+        let count = self.mail.iter().filter(|m| m.to == mail.to).count();
+        if count >= MAX_MAIL {
+            let oldest = self.mail.iter().position(|m| m.to == mail.to).unwrap();
+            self.mail.remove(oldest);
+        }
+        self.mail.push(mail);
+        self.save()
+    }
+
+    // private messages are handed over once, mentions stay until cleared
+    pub fn take_messages (&mut self, nick: &str) -> Vec<Mail> {
+        let (messages, rest) = self.mail.drain(..).partition(|m| m.to == nick && m.room.is_none());
+        self.mail = rest;
+        if !messages.is_empty() {
+            if let Err(error) = self.save() {
+                log!(Error, "cannot save inbox", error = error);
+            }
+        }
+        messages
+    }
+
+    pub fn mentions (&self, nick: &str) -> Vec<Mail> {
+        self.mail.iter().filter(|m| m.to == nick && m.room.is_some()).cloned().collect()
+    }
+
+    pub fn clear_mentions (&mut self, nick: &str) -> Result<(), String> {
+        let count = self.mail.len();
+        self.mail.retain(|m| m.to != nick || m.room.is_none());
+        if self.mail.len() == count {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    // the text of other people's messages, so only the owner may read it
+    fn save (&self) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let content: String = self.mail.iter().map(Mail::to_line).collect();
+        OpenOptions::new().create(true).write(true).truncate(true).mode(0o600).open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("cannot save inbox: {}", e))
+    }
+}
+
+// the nicknames a message mentions with @nick, each once
+pub fn mentioned (text: &str) -> Vec<String> {
+    let mut nicks: Vec<String> = Vec::new();
+    for word in text.split_whitespace().filter(|word| word.starts_with('@')) {
+        // trailing punctuation as in "thanks @bob!" or "@bob:"
+        let nick = word[1..].trim_end_matches(|c: char| ".,:;!?'\")".contains(c));
+        if !nick.is_empty() && !nicks.iter().any(|n| n == nick) {
+            nicks.push(nick.to_string());
+        }
+    }
+    nicks
+}
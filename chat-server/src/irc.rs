@@ -0,0 +1,421 @@
+// A subset of the IRC client protocol (RFC 1459 / RFC 2812): registration,
+// PING/PONG, JOIN, PART, PRIVMSG, NAMES, WHO, AWAY, NICK and QUIT. IRC channels map onto
+// rooms, so `#lobby` is the room plain-text clients know as `lobby`.
+
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use std::sync::mpsc::Sender;
+
+use command::parse_duration;
+use event::{Event, SHUTDOWN_NOTICE};
+use history::Entry;
+use net::{Lines, Stream};
+use ratelimit::Verdict;
+use server::{Client, Delivery, Presence, Protocol, Session, Shared, NICK_SPECIAL, is_valid_nick,
+             normalize_room, queued_message};
+
+
+const SERVER_NAME: &str = "chat-server";
+
+pub struct Message {
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+// `[:prefix] COMMAND param param :trailing param`
+pub fn parse (line: &str) -> Option<Message> {
+    let mut line = line.trim_end_matches(['\r', '\n']);
+    if line.starts_with(':') {
+        line = match line.find(' ') {
+            Some(i) => &line[i + 1..],
+            None => return None,
+        };
+    }
+    let (line, trailing) = match line.find(" :") {
+        Some(i) => (&line[..i], Some(&line[i + 2..])),
+        None => (line, None),
+    };
+    let mut words = line.split(' ').filter(|w| !w.is_empty());
+    let command = words.next()?.to_uppercase();
+    let mut params: Vec<String> = words.map(|w| w.to_string()).collect();
+    if let Some(trailing) = trailing {
+        params.push(trailing.to_string());
+    }
+    Some(Message { command, params })
+}
+
+// Text clients are named after their address until they pick a nickname and
+// people on linked servers may be `nick@server`, neither of which can stand in
+// a prefix or a NAMES list. Such names are shown with what a nickname may not
+// hold replaced, e.g. `_127_0_0_1_4711`.
+fn irc_nick (name: &str) -> String {
+    if is_valid_nick(name) {
+        return name.to_string();
+    }
+    let nick: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || NICK_SPECIAL.contains(c) { c } else { '_' })
+        .collect();
+    if nick.starts_with(|c: char| c.is_ascii_digit() || c == '-') { format!("_{}", nick) } else { nick }
+}
+
+fn prefix (nick: &str) -> String {
+    let nick = irc_nick(nick);
+    format!(":{}!{}@{}", nick, nick, SERVER_NAME)
+}
+
+fn reply (nick: &str, code: &str, params: &str) -> String {
+    format!(":{} {} {} {}\r\n", SERVER_NAME, code, nick, params)
+}
+
+pub fn notice (nick: &str, text: &str) -> String {
+    format!(":{} NOTICE {} :{}\r\n", SERVER_NAME, nick, text)
+}
+
+pub fn render (event: &Event, own_nick: &str) -> Option<String> {
+    let line = match *event {
+        Event::Connected { ref room, ref name, .. } |
+        Event::Joined { ref room, ref name, .. } => {
+            // the client's own JOIN is sent together with the names list
+            if name == own_nick {
+                return None;
+            }
+            format!("{} JOIN #{}", prefix(name), room)
+        },
+        Event::Left { ref room, ref name, .. } => format!("{} PART #{}", prefix(name), room),
+        Event::Disconnected { ref name, ref reason, .. } => format!("{} QUIT :{}", prefix(name), reason),
+        Event::Message { ref room, ref name, ref text, .. } => {
+            // IRC clients display their own messages without an echo
+            if name == own_nick {
+                return None;
+            }
+            format!("{} PRIVMSG #{} :{}", prefix(name), room, text)
+        },
+        Event::Private { ref from, ref to, ref text, .. } => {
+            if from == own_nick {
+                return None;
+            }
+            format!("{} PRIVMSG {} :{}", prefix(from), irc_nick(to), text)
+        },
+        Event::Nick { ref old, ref new, .. } => format!("{} NICK :{}", prefix(old), irc_nick(new)),
+        Event::Shutdown => format!("ERROR :Closing link: {}", SHUTDOWN_NOTICE),
+    };
+    Some(line + "\r\n")
+}
+
+pub fn entered (nick: &str, room: &str, names: &[String]) -> String {
+    format!("{} JOIN #{}\r\n", prefix(nick), room) + &names_reply(nick, room, names)
+}
+
+// `H` for here and `G` for gone, the hop count is always 0
+fn who_reply (nick: &str, room: &str, people: &[Presence]) -> String {
+    let mut text = String::new();
+    for presence in people {
+        let status = if presence.away.is_some() { "G" } else { "H" };
+        let name = irc_nick(&presence.name);
+        text.push_str(&reply(nick, "352", &format!("#{} {} {} {} {} {} :0 {}", room, name, SERVER_NAME,
+                                                   SERVER_NAME, name, status, name)));
+    }
+    text + &reply(nick, "315", &format!("#{} :End of /WHO list.", room))
+}
+
+fn names_reply (nick: &str, room: &str, names: &[String]) -> String {
+    let names: Vec<String> = names.iter().map(|name| irc_nick(name)).collect();
+    reply(nick, "353", &format!("= #{} :{}", room, names.join(" "))) +
+        &reply(nick, "366", &format!("#{} :End of /NAMES list.", room))
+}
+
+pub fn backlog (room: &str, entries: &[Entry]) -> String {
+    entries.iter().map(|entry| {
+        format!("{} PRIVMSG #{} :[{}] {}\r\n", prefix(&entry.name), room, entry.clock(), entry.text)
+    }).collect()
+}
+
+fn welcome (nick: &str) -> String {
+    reply(nick, "001", &format!(":Welcome to the chat server {}", nick)) +
+        &reply(nick, "002", &format!(":Your host is {}", SERVER_NAME)) +
+        &reply(nick, "003", ":This server has no creation date") +
+        &reply(nick, "004", &format!("{} 0.1.0 o o", SERVER_NAME)) +
+        &reply(nick, "422", ":MOTD File is missing")
+}
+
+
+pub fn serve (id: usize, mut stream: Stream, state: Shared, tx: Sender<Event>) {
+    let peer = stream.peer_name(id);
+    let mut reader = match stream.try_clone() {
+        Ok(reader) => Lines::new(BufReader::new(reader)),
+        Err(_) => return,
+    };
+    let mut session = Session::new(id, "*", state, tx);
+
+    // registration: wait for both NICK and USER before entering the server,
+    // a registered nickname also needs the right PASS
+    let mut nick: Option<String> = None;
+    let mut user = false;
+    let mut password: Option<String> = None;
+    loop {
+        let message = match reader.next() {
+            Some(Ok(line)) => match session.throttle(&line) {
+                Verdict::Allow => match parse(&line) {
+                    Some(message) => message,
+                    None => continue,
+                },
+                Verdict::Warn => continue,
+                Verdict::Disconnect => return,
+            },
+            _ => return,
+        };
+        let current = nick.clone().unwrap_or_else(|| "*".to_string());
+        let response = match (message.command.as_str(), message.params.first()) {
+            ("NICK", Some(new)) if !is_valid_nick(new) => {
+                reply(&current, "432", &format!("{} :Erroneous nickname", new))
+            },
+            ("NICK", Some(new)) => {
+                nick = Some(new.to_string());
+                String::new()
+            },
+            ("NICK", None) => reply(&current, "431", ":No nickname given"),
+            ("USER", _) if message.params.len() < 4 => {
+                reply(&current, "461", "USER :Not enough parameters")
+            },
+            ("USER", _) => {
+                user = true;
+                String::new()
+            },
+            ("PASS", Some(pass)) => {
+                password = Some(pass.to_string());
+                String::new()
+            },
+            ("PING", Some(token)) => format!(":{} PONG {} :{}\r\n", SERVER_NAME, SERVER_NAME, token),
+            ("QUIT", _) => return,
+            ("CAP", _) | ("PONG", _) => String::new(),
+            _ => reply(&current, "451", ":You have not registered"),
+        };
+        if !response.is_empty() && stream.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+
+        if let (Some(ref name), true) = (nick.as_ref(), user) {
+            let writer = stream.try_clone().and_then(|writer| Client::new(writer, name, Protocol::Irc));
+            let mut client = match writer {
+                Ok(client) => client,
+                Err(_) => return,
+            };
+            if session.is_registered(name) {
+                let pass = password.clone().unwrap_or_default();
+                if session.authenticate(name, &pass).is_err() {
+                    let response = reply("*", "464", ":Password incorrect");
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        return;
+                    }
+                    nick = None;
+                    continue;
+                }
+                client.account = Some(name.to_string());
+            }
+            match session.register(client) {
+                Ok(()) => break,
+                Err(_) => {
+                    let response = reply("*", "433", &format!("{} :Nickname is already in use", name));
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        return;
+                    }
+                    nick = None;
+                },
+            }
+        }
+    }
+
+    log!(Info, "client connected", id = session.id, addr = peer, protocol = Protocol::Irc,
+         name = session.name);
+    session.send_raw(&welcome(&session.name));
+    session.open_inbox();
+
+    for line in reader {
+        session.touch();
+        let message = match line {
+            Ok(line) => match session.throttle(&line) {
+                Verdict::Allow => match parse(&line) {
+                    Some(message) => message,
+                    None => continue,
+                },
+                Verdict::Warn => continue,
+                Verdict::Disconnect => break,
+            },
+            Err(_) => break,
+        };
+        if message.command == "QUIT" {
+            break;
+        }
+        if message.command != "PING" && message.command != "PONG" {
+            session.active();
+        }
+        handle_message(&mut session, message);
+    }
+
+    log!(Info, "client disconnected", id = session.id, name = session.name);
+    session.close();
+}
+
+fn handle_message (session: &mut Session, message: Message) {
+    let nick = session.name.clone();
+    let params = message.params;
+    match message.command.as_str() {
+        "PING" => {
+            let token = params.first().map(|t| t.as_str()).unwrap_or(SERVER_NAME);
+            session.send_raw(&format!(":{} PONG {} :{}\r\n", SERVER_NAME, SERVER_NAME, token));
+        },
+        "PONG" | "CAP" => {},
+        "JOIN" if params.first().map(|p| p.as_str()) == Some("0") => {
+            for room in session.rooms() {
+                session.part(&room);
+            }
+        },
+        "JOIN" if !params.is_empty() => {
+            for channel in params[0].split(',') {
+                match normalize_room(channel) {
+                    Some(room) => { session.join(&room); },
+                    None => session.send_raw(&reply(&nick, "403", &format!("{} :No such channel", channel))),
+                }
+            }
+        },
+        "PART" if !params.is_empty() => {
+            for channel in params[0].split(',') {
+                let parted = normalize_room(channel).map(|room| session.part(&room)).unwrap_or(false);
+                if !parted {
+                    session.send_raw(&reply(&nick, "442", &format!("{} :You're not on that channel", channel)));
+                }
+            }
+        },
+        "PRIVMSG" if params.len() < 2 => {
+            session.send_raw(&reply(&nick, "412", ":No text to send"));
+        },
+        "PRIVMSG" => {
+            let (target, text) = (&params[0], &params[1]);
+            if let Err(error) = session.may_speak() {
+                session.notice(&error);
+            } else if target.starts_with('#') {
+                match normalize_room(target) {
+                    Some(ref room) if session.rooms().contains(room) => { let _ = session.say(room, text); },
+                    _ => session.send_raw(&reply(&nick, "404", &format!("{} :Cannot send to channel", target))),
+                }
+            } else {
+                match session.whisper(target, text) {
+                    Ok(Delivery::Sent) => {},
+                    Ok(Delivery::Away(reason)) => {
+                        let reason = if reason.is_empty() { "Away" } else { &reason };
+                        session.send_raw(&reply(&nick, "301", &format!("{} :{}", target, reason)));
+                    },
+                    Ok(Delivery::Queued) => session.notice(&queued_message(target)),
+                    Err(_) => session.send_raw(&reply(&nick, "401", &format!("{} :No such nick/channel", target))),
+                }
+            }
+        },
+        "AWAY" => match params.first().filter(|reason| !reason.is_empty()) {
+            Some(reason) => match session.away(reason) {
+                Ok(()) => session.send_raw(&reply(&nick, "306", ":You have been marked as being away")),
+                Err(error) => session.notice(&error),
+            },
+            None => {
+                let _ = session.back();
+                session.send_raw(&reply(&nick, "305", ":You are no longer marked as being away"));
+            },
+        },
+        "WHO" => {
+            let rooms = match params.first() {
+                Some(channels) => channels.split(',').filter_map(normalize_room).collect(),
+                None => session.rooms(),
+            };
+            for room in rooms {
+                session.send_raw(&who_reply(&nick, &room, &session.who(&room)));
+            }
+        },
+        "NAMES" => {
+            let rooms = match params.first() {
+                Some(channels) => channels.split(',').filter_map(normalize_room).collect(),
+                None => session.rooms(),
+            };
+            for room in rooms {
+                session.send_raw(&names_reply(&nick, &room, &session.names(&room)));
+            }
+        },
+        "NICK" if !params.is_empty() => {
+            let new = &params[0];
+            if !is_valid_nick(new) {
+                session.send_raw(&reply(&nick, "432", &format!("{} :Erroneous nickname", new)));
+            } else if session.rename(new).is_err() {
+                session.send_raw(&reply(&nick, "433", &format!("{} :Nickname is already in use", new)));
+            }
+        },
+        "USER" => session.send_raw(&reply(&nick, "462", ":You may not reregister")),
+        // not part of IRC, but raw commands are easy to send from any client
+        "REGISTER" if params.len() == 1 => match session.register_account(&params[0]) {
+            Ok(()) => session.notice(&format!("registered and logged in as {}", nick)),
+            Err(error) => session.notice(&error),
+        },
+        "LOGIN" if params.len() == 2 => match session.login(&params[0], &params[1]) {
+            Ok(()) => session.notice(&format!("logged in as {}", session.name)),
+            Err(error) => session.notice(&error),
+        },
+        "INBOX" if params.is_empty() => match session.inbox() {
+            Ok(ref mentions) if mentions.is_empty() => session.notice("your inbox is empty"),
+            Ok(mentions) => {
+                for mail in mentions {
+                    session.notice(&mail.to_text());
+                }
+            },
+            Err(error) => session.notice(&error),
+        },
+        "INBOX" if params[0].eq_ignore_ascii_case("clear") => match session.clear_inbox() {
+            Ok(()) => session.notice("inbox cleared"),
+            Err(error) => session.notice(&error),
+        },
+        // moderation, KICK disconnects from the whole server rather than one channel
+        "KICK" if params.len() >= 2 => {
+            let reason = params.get(2).map(|r| r.as_str()).unwrap_or("");
+            moderated(session, session.kick(&params[1], reason), &format!("kicked {}", params[1]));
+        },
+        "BAN" if !params.is_empty() => {
+            let duration = params.get(1).and_then(|d| parse_duration(d));
+            let reason = params.get(if duration.is_some() { 2 } else { 1 }).map(|r| r.as_str()).unwrap_or("");
+            match session.ban(&params[0], duration, reason) {
+                Ok(address) => session.notice(&format!("banned {}", address)),
+                Err(error) => session.notice(&error),
+            }
+        },
+        "UNBAN" if !params.is_empty() => {
+            moderated(session, session.unban(&params[0]), &format!("unbanned {}", params[0]));
+        },
+        "MUTE" if !params.is_empty() => {
+            let duration = params.get(1).and_then(|d| parse_duration(d));
+            moderated(session, session.mute(&params[0], duration), &format!("muted {}", params[0]));
+        },
+        "UNMUTE" if !params.is_empty() => {
+            moderated(session, session.unmute(&params[0]), &format!("unmuted {}", params[0]));
+        },
+        "OP" if !params.is_empty() => {
+            moderated(session, session.op(&params[0]), &format!("{} is now an operator", params[0]));
+        },
+        // the standard STATS queries are replaced by the server's own counters
+        "STATS" => match session.stats() {
+            Ok(samples) => {
+                for sample in samples {
+                    session.notice(&format!("{}: {}", sample.help, sample.value));
+                }
+            },
+            Err(error) => session.notice(&error),
+        },
+        "JOIN" | "PART" | "NICK" | "REGISTER" | "LOGIN" | "INBOX" |
+        "KICK" | "BAN" | "UNBAN" | "MUTE" | "UNMUTE" | "OP" => {
+            session.send_raw(&reply(&nick, "461", &format!("{} :Not enough parameters", message.command)));
+        },
+        command => session.send_raw(&reply(&nick, "421", &format!("{} :Unknown command", command))),
+    }
+}
+
+fn moderated (session: &Session, result: Result<(), String>, done: &str) {
+    match result {
+        Ok(()) => session.notice(done),
+        Err(error) => session.notice(&error),
+    }
+}
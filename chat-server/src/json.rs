@@ -0,0 +1,130 @@
+extern crate serde_json;
+extern crate time;
+
+use self::serde_json::{Map, Value};
+
+use command;
+use command::Command;
+use event::{Event, SHUTDOWN_NOTICE};
+use history::Entry;
+use server::normalize_room;
+use transfer::Download;
+
+
+// One JSON object per line, for bots and integrations. Every object has a
+// `type`; events also carry the sender's connection `id` and a `time` in
+// seconds since the epoch.
+fn line (kind: &str, fields: Vec<(&str, Value)>) -> String {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from(kind));
+    for (key, value) in fields {
+        object.insert(key.to_string(), value);
+    }
+    Value::Object(object).to_string() + "\n"
+}
+
+fn now () -> Value {
+    Value::from(time::get_time().sec)
+}
+
+pub fn render (event: &Event) -> String {
+    match *event {
+        Event::Connected { id, ref room, ref name } | Event::Joined { id, ref room, ref name } => {
+            line("join", vec![("id", id.into()), ("time", now()), ("room", room.as_str().into()),
+                              ("name", name.as_str().into())])
+        },
+        Event::Left { id, ref room, ref name } => {
+            line("leave", vec![("id", id.into()), ("time", now()), ("rooms", vec![room.as_str()].into()),
+                               ("name", name.as_str().into()), ("reason", "left".into())])
+        },
+        Event::Disconnected { id, ref rooms, ref name, ref reason } => {
+            line("leave", vec![("id", id.into()), ("time", now()), ("rooms", rooms.clone().into()),
+                               ("name", name.as_str().into()), ("reason", reason.as_str().into())])
+        },
+        Event::Message { id, ref room, ref name, ref text } => {
+            line("message", vec![("id", id.into()), ("time", now()), ("room", room.as_str().into()),
+                                 ("name", name.as_str().into()), ("text", text.as_str().into())])
+        },
+        Event::Private { id, ref from, ref to, ref text } => {
+            line("message", vec![("id", id.into()), ("time", now()), ("to", to.as_str().into()),
+                                 ("name", from.as_str().into()), ("text", text.as_str().into())])
+        },
+        Event::Nick { id, ref rooms, ref old, ref new } => {
+            line("nick", vec![("id", id.into()), ("time", now()), ("rooms", rooms.clone().into()),
+                              ("old", old.as_str().into()), ("new", new.as_str().into())])
+        },
+        Event::Shutdown => line("shutdown", vec![("time", now()), ("text", SHUTDOWN_NOTICE.into())]),
+    }
+}
+
+// stored messages have no sender id any more, they are marked as history instead
+pub fn backlog (entries: &[Entry]) -> String {
+    entries.iter().map(|entry| {
+        line("message", vec![("time", entry.time.into()), ("room", entry.room.as_str().into()),
+                             ("name", entry.name.as_str().into()), ("text", entry.text.as_str().into()),
+                             ("history", true.into())])
+    }).collect()
+}
+
+pub fn notice (text: &str) -> String {
+    line("notice", vec![("text", text.into())])
+}
+
+pub fn error (text: &str) -> String {
+    line("error", vec![("text", text.into())])
+}
+
+pub fn ping (token: u64) -> String {
+    line("ping", vec![("token", token.into())])
+}
+
+pub fn download (part: &Download) -> String {
+    match *part {
+        Download::Start { id, size, name } => {
+            line("file", vec![("id", id.into()), ("size", size.into()), ("name", name.into())])
+        },
+        Download::Chunk { id, offset, ref data } => {
+            line("chunk", vec![("id", id.into()), ("offset", offset.into()), ("data", data.as_str().into())])
+        },
+        Download::End { id } => line("end", vec![("id", id.into())]),
+    }
+}
+
+// requests from the client:
+//     {"type": "message", "text": "hi"}            to the current room
+//     {"type": "message", "to": "bob", "text": "hi"}
+//     {"type": "join", "room": "rust"}
+//     {"type": "nick", "nick": "alice"}
+//     {"type": "command", "text": "/history 5"}    any text command
+//     {"type": "pong"}
+// Strings may not hold control characters: a newline in a message would
+// reach text and IRC clients, and the history file, as lines of its own.
+pub fn parse (line: &str) -> Result<Command, String> {
+    let value: Value = serde_json::from_str(line).map_err(|e| format!("invalid json: {}", e))?;
+    let field = |key: &str| value.get(key).and_then(Value::as_str);
+    let required = |key: &str| match field(key) {
+        Some(text) if text.chars().any(char::is_control) => {
+            Err(format!("string field `{}` may not contain control characters", key))
+        },
+        Some(text) => Ok(text),
+        None => Err(format!("missing string field `{}`", key)),
+    };
+    match field("type") {
+        Some("message") => match field("to") {
+            Some(_) => Ok(Command::Msg(required("to")?.to_string(), required("text")?.to_string())),
+            None => Ok(Command::Say(required("text")?.to_string())),
+        },
+        Some("join") => {
+            let room = required("room")?;
+            normalize_room(room).map(Command::Join).ok_or(format!("invalid room name {}", room))
+        },
+        Some("nick") => Ok(Command::Nick(required("nick")?.to_string())),
+        Some("command") => match command::parse(required("text")?) {
+            Some(command) => command,
+            None => Err("a command starts with a slash".to_string()),
+        },
+        Some("pong") => Ok(Command::Pong),
+        Some(kind) => Err(format!("unknown type `{}`", kind)),
+        None => Err("missing string field `type`".to_string()),
+    }
+}
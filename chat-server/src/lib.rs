@@ -0,0 +1,185 @@
+// The chat server as a library: `main` parses the command line and waits for
+// a signal, everything in between lives here so tests can run servers on
+// ephemeral ports in their own process.
+
+#[macro_use]
+mod log;
+
+mod accounts;
+mod bans;
+mod bots;
+mod command;
+pub mod config;
+mod event;
+mod history;
+mod inbox;
+mod irc;
+mod json;
+mod link;
+mod metrics;
+mod net;
+mod plugin;
+mod ratelimit;
+mod server;
+mod tls;
+mod transcript;
+mod transfer;
+mod websocket;
+
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::{channel, Sender};
+
+use accounts::Accounts;
+use bans::Bans;
+use event::Event;
+use history::History;
+use inbox::Inbox;
+use net::Listener;
+use server::{State, Shared};
+use transfer::Transfers;
+
+pub use config::Config;
+pub use server::Protocol;
+
+
+// a running server, its threads keep going until the process exits
+pub struct Server {
+    config: Arc<Config>,
+    state: Shared,
+    broadcast_tx: Sender<Event>,
+    // what every listener is bound to, with port 0 resolved
+    listeners: Vec<(Protocol, String)>,
+}
+
+// binds every listener, opens the files named in `config` and starts serving
+pub fn start (config: Config) -> Result<Server, String> {
+    log::init(config.log_level, config.hide_addresses);
+    let listeners = bind_listeners(&config)?;
+    let link_listeners = config.link_listen.iter()
+        .map(|&addr| Listener::bind_tcp(addr))
+        .collect::<Result<Vec<Listener>, String>>()?;
+    let metrics_listener = match config.metrics_listen {
+        Some(addr) => Some(Listener::bind_tcp(addr)?),
+        None => None,
+    };
+
+    let history = match config.history_file {
+        Some(ref path) => History::open(config.history_size, path)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+        None => History::new(config.history_size),
+    };
+
+    let accounts = match config.accounts_file {
+        Some(ref path) => Accounts::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+        None => Accounts::disabled(),
+    };
+    // otherwise whoever registered the nickname first would become an operator
+    if let Some(nick) = config.operators.iter().find(|nick| !accounts.is_registered(nick)) {
+        return Err(format!("operator {} is not registered, start the server without --operator \
+                            and /register it first", nick));
+    }
+
+    let bans = match config.bans_file {
+        Some(ref path) => Bans::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+        None => Bans::new(),
+    };
+
+    let inbox = match config.inbox_file {
+        Some(ref path) => Inbox::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+        None => Inbox::new(),
+    };
+
+    let transfers = match config.spool_dir {
+        Some(ref path) => Transfers::open(path, config.max_file_size as u64)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+        None => Transfers::disabled(),
+    };
+
+    let plugins = plugin::load(&config)?;
+
+    let config = Arc::new(config);
+    let state = Arc::new(Mutex::new(State::new(config.clone(), history, accounts, bans, inbox,
+                                                          transfers)));
+    let (broadcast_tx, broadcast_rx) = channel();
+    server::spawn_broadcast_thread(state.clone(), broadcast_rx, plugins);
+    server::spawn_keepalive_thread(state.clone());
+    if let Some(listener) = metrics_listener {
+        log!(Info, "serving metrics", url = format!("http://{}/metrics", listener.name()));
+        metrics::spawn_http_thread(listener, state.clone());
+    }
+
+    for listener in link_listeners {
+        log!(Info, "accepting links", listen = listener.name());
+        link::spawn_listen_thread(listener, state.clone(), broadcast_tx.clone());
+    }
+    for &addr in config.link.iter() {
+        link::spawn_connect_thread(addr, state.clone(), broadcast_tx.clone());
+    }
+
+    // id 0 is for plugins and 1 for people on linked servers
+    let next_id = Arc::new(AtomicUsize::new(2));
+    let mut names = Vec::new();
+    for (listener, protocol) in listeners {
+        log!(Info, "listening", protocol = protocol, listen = listener.name());
+        names.push((protocol, listener.name()));
+        server::spawn_accept_thread(listener, protocol, next_id.clone(), state.clone(), broadcast_tx.clone());
+    }
+
+    Ok(Server { config, state, broadcast_tx, listeners: names })
+}
+
+fn bind_listeners (config: &Config) -> Result<Vec<(Listener, Protocol)>, String> {
+    let mut listeners = Vec::new();
+    for &addr in config.listen.iter() {
+        listeners.push((Listener::bind_tcp(addr)?, Protocol::Text));
+    }
+    if let Some(ref path) = config.unix {
+        listeners.push((Listener::bind_unix(path)?, Protocol::Text));
+    }
+    if let (Some(cert), Some(key)) = (config.tls_cert.as_ref(), config.tls_key.as_ref()) {
+        let tls_config = tls::load_config(cert, key)?;
+        for &addr in config.tls_listen.iter() {
+            listeners.push((Listener::bind_tls(addr, tls_config.clone())?, Protocol::Text));
+        }
+    }
+    for &addr in config.irc_listen.iter() {
+        listeners.push((Listener::bind_tcp(addr)?, Protocol::Irc));
+    }
+    for &addr in config.ws_listen.iter() {
+        listeners.push((Listener::bind_tcp(addr)?, Protocol::WebSocket));
+    }
+    Ok(listeners)
+}
+
+impl Server {
+    // the first TCP listener for `protocol`; plain TCP comes before TLS
+    pub fn address (&self, protocol: Protocol) -> Option<SocketAddr> {
+        self.listeners.iter()
+            .filter(|l| l.0 == protocol)
+            .filter_map(|l| l.1.parse().ok())
+            .next()
+    }
+
+    // clients which have entered the chat, as opposed to connections
+    pub fn client_count (&self) -> usize {
+        self.state.lock().unwrap().clients.len()
+    }
+
+    pub fn names (&self, room: &str) -> Vec<String> {
+        self.state.lock().unwrap().names(room)
+    }
+
+    // tells every client, waits up to `timeout` for them to go and saves the history
+    pub fn shutdown (&self, timeout: Duration) {
+        log!(Info, "shutting down");
+        server::shutdown(&self.state, &self.broadcast_tx, timeout);
+        if let Some(ref path) = self.config.unix {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,637 @@
+// Server-to-server links. Linked servers tell each other about their users and
+// relay what happens in rooms, so people on either side see each other.
+//
+// The servers of a network form a tree: a link to a server which is already
+// part of the network is refused, and whatever comes in on one link is passed
+// on to every other link, so nothing goes round in circles or arrives twice.
+// When a link is lost, everyone on the servers behind it leaves in a netsplit.
+//
+// The protocol is one JSON object per line. The connecting server sends a
+// `hello` with its name, the other one answers with its own `hello` or an
+// `error`, then both announce the servers and users they know of.
+
+extern crate serde_json;
+
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+
+use self::serde_json::{Map, Value};
+
+use event::Event;
+use metrics;
+use metrics::METRICS;
+use net::{Lines, Listener, Outbox, Stream, WRITE_TIMEOUT};
+use server::{Shared, State};
+
+
+// events from users on linked servers carry this id
+pub const LINK_ID: usize = 1;
+
+pub const SPLIT_REASON: &str = "left in a netsplit";
+
+// how long to wait before trying again when a link is lost or refused
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+enum Message {
+    Hello { name: String },
+    Error { text: String },
+    // another server in the network, or one which has gone
+    Server { name: String },
+    Squit { name: String },
+    // someone already there when the servers were linked
+    User { server: String, nick: String, rooms: Vec<String>, away: Option<String> },
+    Connect { server: String, nick: String, room: String },
+    Join { server: String, nick: String, room: String },
+    Leave { server: String, nick: String, room: String },
+    Quit { server: String, nick: String, reason: String },
+    Say { server: String, nick: String, room: String, text: String },
+    Private { server: String, nick: String, to_server: String, to: String, text: String },
+    Nick { server: String, nick: String, new: String },
+    Away { server: String, nick: String, reason: Option<String> },
+}
+
+impl Message {
+    // the server of the user the message is about
+    fn server (&self) -> Option<&str> {
+        match *self {
+            Message::Hello { .. } | Message::Error { .. } |
+            Message::Server { .. } | Message::Squit { .. } => None,
+            Message::User { ref server, .. } | Message::Connect { ref server, .. } |
+            Message::Join { ref server, .. } | Message::Leave { ref server, .. } |
+            Message::Quit { ref server, .. } | Message::Say { ref server, .. } |
+            Message::Private { ref server, .. } | Message::Nick { ref server, .. } |
+            Message::Away { ref server, .. } => Some(server),
+        }
+    }
+
+    fn to_line (&self) -> String {
+        let (kind, fields): (&str, Vec<(&str, Value)>) = match *self {
+            Message::Hello { ref name } => ("hello", vec![("name", name.as_str().into())]),
+            Message::Error { ref text } => ("error", vec![("text", text.as_str().into())]),
+            Message::Server { ref name } => ("server", vec![("name", name.as_str().into())]),
+            Message::Squit { ref name } => ("squit", vec![("name", name.as_str().into())]),
+            Message::User { ref server, ref nick, ref rooms, ref away } => {
+                ("user", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                              ("rooms", rooms.clone().into()), ("away", away.clone().into())])
+            },
+            Message::Connect { ref server, ref nick, ref room } => {
+                ("connect", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                                 ("room", room.as_str().into())])
+            },
+            Message::Join { ref server, ref nick, ref room } => {
+                ("join", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                              ("room", room.as_str().into())])
+            },
+            Message::Leave { ref server, ref nick, ref room } => {
+                ("leave", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                               ("room", room.as_str().into())])
+            },
+            Message::Quit { ref server, ref nick, ref reason } => {
+                ("quit", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                              ("reason", reason.as_str().into())])
+            },
+            Message::Say { ref server, ref nick, ref room, ref text } => {
+                ("message", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                                 ("room", room.as_str().into()), ("text", text.as_str().into())])
+            },
+            Message::Private { ref server, ref nick, ref to_server, ref to, ref text } => {
+                ("private", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                                 ("to_server", to_server.as_str().into()), ("to", to.as_str().into()),
+                                 ("text", text.as_str().into())])
+            },
+            Message::Nick { ref server, ref nick, ref new } => {
+                ("nick", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                              ("new", new.as_str().into())])
+            },
+            Message::Away { ref server, ref nick, ref reason } => {
+                ("away", vec![("server", server.as_str().into()), ("nick", nick.as_str().into()),
+                              ("reason", reason.clone().into())])
+            },
+        };
+        let mut object = Map::new();
+        object.insert("type".to_string(), Value::from(kind));
+        for (key, value) in fields {
+            object.insert(key.to_string(), value);
+        }
+        Value::Object(object).to_string() + "\n"
+    }
+
+    fn parse (line: &str) -> Option<Message> {
+        let object: Value = serde_json::from_str(line).ok()?;
+        let field = |key: &str| object.get(key).and_then(Value::as_str).map(String::from);
+        let server = field("server");
+        let nick = field("nick");
+        let message = match object.get("type")?.as_str()? {
+            "hello" => Message::Hello { name: field("name")? },
+            "error" => Message::Error { text: field("text")? },
+            "server" => Message::Server { name: field("name")? },
+            "squit" => Message::Squit { name: field("name")? },
+            "user" => Message::User {
+                server: server?,
+                nick: nick?,
+                rooms: object.get("rooms")?.as_array()?.iter()
+                    .filter_map(|room| room.as_str().map(String::from))
+                    .collect(),
+                away: field("away"),
+            },
+            "connect" => Message::Connect { server: server?, nick: nick?, room: field("room")? },
+            "join" => Message::Join { server: server?, nick: nick?, room: field("room")? },
+            "leave" => Message::Leave { server: server?, nick: nick?, room: field("room")? },
+            "quit" => Message::Quit { server: server?, nick: nick?, reason: field("reason")? },
+            "message" => Message::Say { server: server?, nick: nick?, room: field("room")?, text: field("text")? },
+            "private" => Message::Private {
+                server: server?,
+                nick: nick?,
+                to_server: field("to_server")?,
+                to: field("to")?,
+                text: field("text")?,
+            },
+            "nick" => Message::Nick { server: server?, nick: nick?, new: field("new")? },
+            "away" => Message::Away { server: server?, nick: nick?, reason: field("reason") },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+
+// someone on another server
+pub struct RemoteUser {
+    pub server: String,
+    pub nick: String,
+    // what they are called here, `nick@server` when the nickname is taken
+    pub name: String,
+    pub rooms: Vec<String>,
+    pub away: Option<String>,
+    pub active: Instant,
+}
+
+struct Peer {
+    name: String,
+    stream: Stream,
+    // everything sent over the link is written from here, outside the state lock
+    outbox: Outbox,
+}
+
+impl Peer {
+    // a link which falls too far behind is dropped, its reader thread
+    // sees the stream end and splits the network as usual
+    fn write (&mut self, text: &str) {
+        if self.outbox.send(text.as_bytes().to_vec()).is_err() {
+            let _ = self.stream.shutdown();
+        }
+    }
+
+    // once everything already queued has been written
+    fn shutdown (&self) {
+        if self.outbox.close().is_err() {
+            let _ = self.stream.shutdown();
+        }
+    }
+}
+
+pub struct Links {
+    // this server's name
+    name: String,
+    peers: HashMap<usize, Peer>,
+    next_id: usize,
+    // every other server in the network and the link it is reached through
+    servers: HashMap<String, usize>,
+    pub users: Vec<RemoteUser>,
+}
+
+impl Links {
+    pub fn new (name: &str) -> Links {
+        Links {
+            name: name.to_string(),
+            peers: HashMap::new(),
+            next_id: 1,
+            servers: HashMap::new(),
+            users: Vec::new(),
+        }
+    }
+
+    pub fn find (&self, name: &str) -> Option<&RemoteUser> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    fn position (&self, server: &str, nick: &str) -> Option<usize> {
+        self.users.iter().position(|user| user.server == server && user.nick == nick)
+    }
+
+    // only queued under the state lock, a slow link holds up nobody else
+    fn send (&mut self, except: Option<usize>, message: &Message) {
+        let line = message.to_line();
+        for (&id, peer) in self.peers.iter_mut() {
+            if Some(id) != except {
+                peer.write(&line);
+            }
+        }
+    }
+
+    pub fn close (&self) {
+        for peer in self.peers.values() {
+            let _ = peer.stream.shutdown();
+        }
+    }
+}
+
+pub fn is_valid_server_name (name: &str) -> bool {
+    !name.is_empty() && name.len() <= 50 &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_')
+}
+
+// what someone on `server` is called here, whether or not they are known yet
+fn name_of (state: &State, server: &str, nick: &str) -> String {
+    match state.links.position(server, nick) {
+        Some(index) => state.links.users[index].name.clone(),
+        // a registered nickname is kept for its owner, who may log in any time
+        None if state.is_taken(nick) || state.accounts.is_registered(nick) => format!("{}@{}", nick, server),
+        None => nick.to_string(),
+    }
+}
+
+// returns the index of the user, and whether they were new
+fn introduce (state: &mut State, server: &str, nick: &str) -> (usize, bool) {
+    if let Some(index) = state.links.position(server, nick) {
+        return (index, false);
+    }
+    state.links.users.push(RemoteUser {
+        server: server.to_string(),
+        nick: nick.to_string(),
+        name: name_of(state, server, nick),
+        rooms: Vec::new(),
+        away: None,
+        active: Instant::now(),
+    });
+    (state.links.users.len() - 1, true)
+}
+
+fn remove_users<F: Fn(&RemoteUser) -> bool> (state: &mut State, gone: F, reason: &str) -> Vec<Event> {
+    let (gone, staying) = state.links.users.drain(..).partition(gone);
+    state.links.users = staying;
+    gone.into_iter().map(|user: RemoteUser| {
+        Event::Disconnected { id: LINK_ID, rooms: user.rooms, name: user.name, reason: reason.to_string() }
+    }).collect()
+}
+
+// may a server called `name` join the network
+fn check (state: &State, name: &str) -> Result<(), String> {
+    if !is_valid_server_name(name) {
+        Err(format!("invalid server name {}", name))
+    } else if name == state.links.name {
+        Err(format!("this server is called {} too", name))
+    } else if state.links.servers.contains_key(name) {
+        Err(format!("server {} is already linked", name))
+    } else {
+        Ok(())
+    }
+}
+
+// adds the link and tells the new server what this side of the network looks like
+fn add (state: &mut State, name: &str, stream: Stream, outbox: Outbox) -> usize {
+    let id = state.links.next_id;
+    state.links.next_id += 1;
+    let own = state.links.name.clone();
+    let mut burst: Vec<Message> = state.links.servers.keys()
+        .map(|server| Message::Server { name: server.clone() })
+        .collect();
+    burst.extend(state.clients.values().map(|client| Message::User {
+        server: own.clone(),
+        nick: client.name.clone(),
+        rooms: client.rooms.clone(),
+        away: client.away.clone(),
+    }));
+    burst.extend(state.links.users.iter().map(|user| Message::User {
+        server: user.server.clone(),
+        nick: user.nick.clone(),
+        rooms: user.rooms.clone(),
+        away: user.away.clone(),
+    }));
+
+    state.links.send(None, &Message::Server { name: name.to_string() });
+    state.links.servers.insert(name.to_string(), id);
+    state.links.peers.insert(id, Peer { name: name.to_string(), stream, outbox });
+    if let Some(peer) = state.links.peers.get_mut(&id) {
+        let text: String = burst.iter().map(Message::to_line).collect();
+        peer.write(&text);
+    }
+    id
+}
+
+// drops the link and everyone reached through it
+fn split (state: &mut State, link: usize) -> Vec<Event> {
+    if let Some(peer) = state.links.peers.remove(&link) {
+        peer.shutdown();
+    }
+    let lost: Vec<String> = state.links.servers.iter()
+        .filter(|server| *server.1 == link)
+        .map(|server| server.0.clone())
+        .collect();
+    for name in lost.iter() {
+        state.links.servers.remove(name);
+        state.links.send(None, &Message::Squit { name: name.clone() });
+    }
+    remove_users(state, |user| lost.contains(&user.server), SPLIT_REASON)
+}
+
+// applies a message from `link` and passes it on to the other links,
+// returns the events to show here
+fn receive (state: &mut State, link: usize, message: &Message) -> Result<Vec<Event>, String> {
+    // anything about a server which is not behind this link is out of date
+    if let Some(server) = message.server() {
+        if state.links.servers.get(server) != Some(&link) {
+            return Ok(Vec::new());
+        }
+    }
+    let events = match *message {
+        Message::Hello { .. } => return Err("unexpected hello".to_string()),
+        Message::Error { ref text } => return Err(text.clone()),
+        Message::Server { ref name } => {
+            check(state, name)?;
+            state.links.servers.insert(name.clone(), link);
+            Vec::new()
+        },
+        Message::Squit { ref name } => {
+            if state.links.servers.get(name) != Some(&link) {
+                return Ok(Vec::new());
+            }
+            state.links.servers.remove(name);
+            remove_users(state, |user| user.server == *name, SPLIT_REASON)
+        },
+        Message::User { ref server, ref nick, ref rooms, ref away } => {
+            let (index, _) = introduce(state, server, nick);
+            let user = &mut state.links.users[index];
+            user.away = away.clone();
+            let mut events = Vec::new();
+            for room in rooms.iter() {
+                if user.rooms.contains(room) {
+                    continue;
+                }
+                user.rooms.push(room.clone());
+                events.push(Event::Joined { id: LINK_ID, room: room.clone(), name: user.name.clone() });
+            }
+            events
+        },
+        Message::Connect { ref server, ref nick, ref room } | Message::Join { ref server, ref nick, ref room } => {
+            let (index, _) = introduce(state, server, nick);
+            let user = &mut state.links.users[index];
+            user.active = Instant::now();
+            if user.rooms.contains(room) {
+                Vec::new()
+            } else {
+                user.rooms.push(room.clone());
+                let (room, name) = (room.clone(), user.name.clone());
+                match *message {
+                    Message::Connect { .. } => vec![Event::Connected { id: LINK_ID, room, name }],
+                    _ => vec![Event::Joined { id: LINK_ID, room, name }],
+                }
+            }
+        },
+        Message::Leave { ref server, ref nick, ref room } => match state.links.position(server, nick) {
+            Some(index) if state.links.users[index].rooms.contains(room) => {
+                let user = &mut state.links.users[index];
+                user.rooms.retain(|r| r != room);
+                vec![Event::Left { id: LINK_ID, room: room.clone(), name: user.name.clone() }]
+            },
+            _ => Vec::new(),
+        },
+        Message::Quit { ref server, ref nick, ref reason } => {
+            remove_users(state, |user| user.server == *server && user.nick == *nick, reason)
+        },
+        Message::Say { ref server, ref nick, ref room, ref text } => {
+            if let Some(index) = state.links.position(server, nick) {
+                state.links.users[index].active = Instant::now();
+            }
+            vec![Event::Message { id: LINK_ID, room: room.clone(), name: name_of(state, server, nick), text: text.clone() }]
+        },
+        Message::Private { ref server, ref nick, ref to_server, ref to, ref text } => {
+            if *to_server == state.links.name {
+                vec![Event::Private { id: LINK_ID, from: name_of(state, server, nick), to: to.clone(), text: text.clone() }]
+            } else {
+                Vec::new()
+            }
+        },
+        Message::Nick { ref server, ref nick, ref new } => match state.links.position(server, nick) {
+            Some(index) => {
+                // out of the list while the new name is worked out, so it does not count as taken
+                let user = state.links.users.remove(index);
+                let (old, rooms, name) = (user.name.clone(), user.rooms.clone(), name_of(state, server, new));
+                state.links.users.insert(index, RemoteUser { nick: new.clone(), name: name.clone(), ..user });
+                vec![Event::Nick { id: LINK_ID, rooms, old, new: name }]
+            },
+            None => Vec::new(),
+        },
+        Message::Away { ref server, ref nick, ref reason } => {
+            if let Some(index) = state.links.position(server, nick) {
+                state.links.users[index].away = reason.clone();
+            }
+            Vec::new()
+        },
+    };
+    state.links.send(Some(link), message);
+    Ok(events)
+}
+
+// tells the other servers what happened here, events from them are passed on by `receive`
+pub fn relay (state: &mut State, event: &Event) {
+    if state.links.peers.is_empty() || event.sender() == Some(LINK_ID) {
+        return;
+    }
+    let server = state.links.name.clone();
+    let message = match *event {
+        Event::Connected { ref room, ref name, .. } => {
+            Message::Connect { server, nick: name.clone(), room: room.clone() }
+        },
+        Event::Joined { ref room, ref name, .. } => Message::Join { server, nick: name.clone(), room: room.clone() },
+        Event::Left { ref room, ref name, .. } => Message::Leave { server, nick: name.clone(), room: room.clone() },
+        Event::Disconnected { ref name, ref reason, .. } => {
+            Message::Quit { server, nick: name.clone(), reason: reason.clone() }
+        },
+        Event::Message { ref room, ref name, ref text, .. } => {
+            Message::Say { server, nick: name.clone(), room: room.clone(), text: text.clone() }
+        },
+        Event::Private { ref from, ref to, ref text, .. } => match state.links.find(to) {
+            Some(user) => Message::Private {
+                server,
+                nick: from.clone(),
+                to_server: user.server.clone(),
+                to: user.nick.clone(),
+                text: text.clone(),
+            },
+            None => return,
+        },
+        Event::Nick { ref old, ref new, .. } => Message::Nick { server, nick: old.clone(), new: new.clone() },
+        Event::Shutdown => return,
+    };
+    state.links.send(None, &message);
+}
+
+// away status is not an event, so it is relayed on its own
+pub fn away (state: &mut State, nick: &str, reason: Option<&str>) {
+    if state.links.peers.is_empty() {
+        return;
+    }
+    let server = state.links.name.clone();
+    state.links.send(None, &Message::Away { server, nick: nick.to_string(), reason: reason.map(String::from) });
+}
+
+
+pub fn spawn_listen_thread (listener: Listener, state: Shared, tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            if let Ok(stream) = listener.accept() {
+                let (state, tx) = (state.clone(), tx.clone());
+                thread::spawn(move || accept(stream, state, tx));
+            }
+        }
+    });
+}
+
+// links to `addr`, and again whenever the link is lost or refused
+pub fn spawn_connect_thread (addr: SocketAddr, state: Shared, tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            match TcpStream::connect(addr) {
+                Ok(stream) => connect(Stream::Tcp(stream), &state, &tx),
+                Err(e) => log!(Debug, "cannot link", addr = addr, error = e),
+            }
+            thread::sleep(RETRY_INTERVAL);
+        }
+    });
+}
+
+fn accept (stream: Stream, state: Shared, tx: Sender<Event>) {
+    let addr = stream.peer_name(0);
+    let (mut reader, outbox) = match (stream.try_clone(), stream.try_clone()) {
+        (Ok(reader), Ok(writer)) => (Lines::new(BufReader::new(reader)), Outbox::new(writer)),
+        _ => return,
+    };
+    let name = match hello(&mut reader) {
+        Ok(name) => name,
+        Err(error) => {
+            log!(Warn, "link refused", addr = addr, error = error);
+            return;
+        },
+    };
+    let link = {
+        let mut state = state.lock().unwrap();
+        let own = state.links.name.clone();
+        match check(&state, &name) {
+            Ok(()) => {
+                let _ = outbox.send(Message::Hello { name: own }.to_line().into_bytes());
+                add(&mut state, &name, stream, outbox)
+            },
+            Err(error) => {
+                log!(Warn, "link refused", addr = addr, server = name, error = error);
+                let _ = outbox.send(Message::Error { text: error }.to_line().into_bytes());
+                let _ = outbox.close();
+                return;
+            },
+        }
+    };
+    log!(Info, "server linked", addr = addr, server = name);
+    run(link, reader, &state, &tx);
+}
+
+fn connect (mut stream: Stream, state: &Shared, tx: &Sender<Event>) {
+    let addr = stream.peer_name(0);
+    // accepted links have one already
+    if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+        return;
+    }
+    let (mut reader, outbox) = match (stream.try_clone(), stream.try_clone()) {
+        (Ok(reader), Ok(writer)) => (Lines::new(BufReader::new(reader)), Outbox::new(writer)),
+        _ => return,
+    };
+    let own = state.lock().unwrap().links.name.clone();
+    if stream.write_all(Message::Hello { name: own }.to_line().as_bytes()).is_err() {
+        return;
+    }
+    let link = match hello(&mut reader) {
+        Ok(name) => {
+            let mut state = state.lock().unwrap();
+            match check(&state, &name) {
+                Ok(()) => Ok((add(&mut state, &name, stream, outbox), name)),
+                Err(error) => Err(error),
+            }
+        },
+        Err(error) => Err(error),
+    };
+    match link {
+        Ok((link, name)) => {
+            log!(Info, "server linked", addr = addr, server = name);
+            run(link, reader, state, tx);
+        },
+        Err(error) => log!(Warn, "link refused", addr = addr, error = error),
+    }
+}
+
+// the first line from the other server, which names it
+fn hello (reader: &mut Lines<BufReader<Stream>>) -> Result<String, String> {
+    let line = match reader.next() {
+        Some(line) => line.map_err(|e| e.to_string())?,
+        None => return Err("the link was closed".to_string()),
+    };
+    match Message::parse(&line) {
+        Some(Message::Hello { name }) => Ok(name),
+        Some(Message::Error { text }) => Err(text),
+        _ => Err("expected a hello".to_string()),
+    }
+}
+
+fn run (link: usize, reader: Lines<BufReader<Stream>>, state: &Shared, tx: &Sender<Event>) {
+    for line in reader.map_while(Result::ok) {
+        let message = match Message::parse(&line) {
+            Some(message) => message,
+            None => {
+                log!(Debug, "ignoring link message", line = line);
+                continue;
+            },
+        };
+        let mut state = state.lock().unwrap();
+        let events = match receive(&mut state, link, &message) {
+            Ok(events) => events,
+            // dropped under the same lock, so another link cannot be
+            // refused for the servers behind this one in the meantime
+            Err(error) => {
+                let events = close(&mut state, link, Some(error));
+                drop(state);
+                queue(tx, events);
+                return;
+            },
+        };
+        drop(state);
+        queue(tx, events);
+    }
+    let events = close(&mut state.lock().unwrap(), link, None);
+    queue(tx, events);
+}
+
+fn close (state: &mut State, link: usize, error: Option<String>) -> Vec<Event> {
+    let name = state.links.peers.get(&link).map(|peer| peer.name.clone()).unwrap_or_default();
+    match error {
+        Some(error) => {
+            if let Some(peer) = state.links.peers.get_mut(&link) {
+                peer.write(&Message::Error { text: error.clone() }.to_line());
+            }
+            log!(Warn, "link dropped", server = name, error = error);
+        },
+        None => log!(Info, "link lost", server = name),
+    }
+    split(state, link)
+}
+
+fn queue (tx: &Sender<Event>, events: Vec<Event>) {
+    for event in events {
+        metrics::count(&METRICS.queued, 1);
+        let _ = tx.send(event);
+    }
+}
@@ -0,0 +1,99 @@
+extern crate time;
+
+pub use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn parse (name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn name (self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+static HIDE_ADDRESSES: AtomicBool = AtomicBool::new(false);
+
+pub fn init (level: Level, hide_addresses: bool) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    HIDE_ADDRESSES.store(hide_addresses, Ordering::Relaxed);
+}
+
+pub fn hides_addresses () -> bool {
+    HIDE_ADDRESSES.load(Ordering::Relaxed)
+}
+
+// clients are named after their address until they pick a nickname
+pub fn is_address (value: &str) -> bool {
+    value.parse::<SocketAddr>().is_ok() || value.parse::<IpAddr>().is_ok()
+}
+
+// `log!(Info, "client connected", id = id, addr = peer)`, with any number of
+// fields whose values implement Display
+macro_rules! log {
+    ($level:ident, $message:expr $(, $key:ident = $value:expr)*) => {
+        $crate::log::log($crate::log::Level::$level, $message,
+                         &[$((stringify!($key), &$value as &dyn $crate::log::Display)),*])
+    };
+}
+
+// One line per record in the logfmt style, e.g.
+//     time=2024-05-01T12:00:00Z level=info msg="client connected" id=3 addr=127.0.0.1:50312
+// With addresses hidden, `addr` fields are left out and any address in the
+// other values, e.g. a client still named after its address or a refusal
+// naming it, is replaced by a dash; only the server's own `listen` and `url`
+// are kept.
+pub fn log (level: Level, message: &str, fields: &[(&str, &dyn Display)]) {
+    if level as usize > LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut line = format!("time={} level={} msg={}", time::now_utc().rfc3339(), level.name(), quote(message));
+    for &(key, value) in fields {
+        let value = value.to_string();
+        if hides_addresses() && key == "addr" {
+            continue;
+        }
+        let value = if hides_addresses() && key != "listen" && key != "url" { redact(&value) } else { value };
+        line.push_str(&format!(" {}={}", key, quote(&value)));
+    }
+    println!("{}", line);
+}
+
+// every word of `value` which is an address becomes a dash, whatever punctuation is around it
+fn redact (value: &str) -> String {
+    value.split(' ').map(|word| {
+        let address = word.trim_matches(|c: char| !(c.is_ascii_hexdigit() || ".:[]".contains(c)));
+        if is_address(address) { word.replacen(address, "-", 1) } else { word.to_string() }
+    }).collect::<Vec<_>>().join(" ")
+}
+
+fn quote (value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        format!("{:?}", value)
+    } else {
+        value.to_string()
+    }
+}
@@ -1,73 +1,43 @@
+extern crate chat_server;
+extern crate signal_hook;
 
 use std::io::prelude::*;
-use std::net::{TcpStream, TcpListener};
+use std::io;
+use std::env;
+use std::process;
+use std::fmt::Display;
+use std::time::Duration;
 
-use std::thread;
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{channel, Sender, Receiver};
+use chat_server::config;
+use chat_server::Config;
 
-use std::collections::HashMap;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 
-fn main () {
-    let addr = "127.0.0.1:8080";
-    let clients = Arc::new(Mutex::new(HashMap::<usize, TcpStream>::new()));
-    let listener = TcpListener::bind(addr).unwrap();
-    println!("running server at {}", addr);
-    let (broadcast_tx, broadcast_rx) = channel();
-    spawn_broadcast_thread(clients.clone(), broadcast_rx);
+// how long clients get to receive the shutdown notice before the server exits
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
-    for (id, stream) in listener.incoming().enumerate() {
-        match stream {
-            Ok(stream) => {
-                clients.lock().unwrap().insert(id, stream.try_clone().unwrap());
-                spawn_client_thread(stream, broadcast_tx.clone());
-            }
-            Err(_) => { /* connection failed */ }
-        }
-    }
-}
 
-fn spawn_client_thread (mut stream: TcpStream, tx: Sender<String>) {
-    let addr = stream.peer_addr().unwrap();
-    thread::spawn(move || {
-        let mut buffer = [0; 2048];
-        println!("{} connected", addr);
-        tx.send(format!("{} connected\n", addr)).unwrap();
-        loop {
-            match stream.read(&mut buffer) {
-                Err(_) => break,
-                Ok(0) => break, // EOF
-                Ok(bytes) => {
-                    match std::str::from_utf8(&buffer[0..bytes]) {
-                        Err(_) => break,
-                        Ok(utf8_str) => tx.send(format!("{}: {}", addr, utf8_str)).unwrap(),
-                    }
-                },
-            }
-        }
-        println!("{} disconnected", addr);
-        tx.send(format!("{} disconnected\n", addr)).unwrap();
-    });
+fn main () {
+    if env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", config::USAGE);
+        return;
+    }
+    let config = unwrap_exit(Config::from_args(env::args().skip(1)));
+    let mut signals = unwrap_exit(Signals::new([SIGINT, SIGTERM])
+                                  .map_err(|e| format!("cannot handle signals: {}", e)));
+    let server = unwrap_exit(chat_server::start(config));
+
+    // the server's threads run until the process exits
+    signals.forever().next();
+    server.shutdown(SHUTDOWN_TIMEOUT);
+    process::exit(0);
 }
 
-fn spawn_broadcast_thread (clients: Arc<Mutex<HashMap<usize, TcpStream>>>, rx: Receiver<String>) {
-    thread::spawn(move || {
-        loop {
-            let message = rx.recv().unwrap();
-            let mut writers = clients.lock().unwrap();
-            let mut invalid_writer_ids = Vec::new();
-
-            for (&id, stream) in writers.iter_mut() {
-                match stream.write_all(message.as_bytes()) {
-                    Ok(()) => { /* sucess */ },
-                    Err(_) => invalid_writer_ids.push(id),
-                };
-            }
-
-            for id in invalid_writer_ids {
-                writers.remove(&id);
-            }
-        }
-    });
+fn unwrap_exit<T, E: Display> (result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        let _ = writeln!(io::stderr(), "Error: {}", e);
+        process::exit(1);
+    })
 }
@@ -0,0 +1,132 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::thread;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use net;
+use net::{Listener, Stream};
+use server::Shared;
+
+
+// Counters shared by every thread of the process, read by the metrics
+// endpoint and by /stats.
+pub struct Metrics {
+    pub accepted: AtomicU64,
+    pub refused: AtomicU64,
+    pub messages: AtomicU64,
+    pub private_messages: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub dropped_clients: AtomicU64,
+    pub flood_drops: AtomicU64,
+    pub timeouts: AtomicU64,
+    // events sent to the broadcast thread and not yet delivered
+    pub queued: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    accepted: AtomicU64::new(0),
+    refused: AtomicU64::new(0),
+    messages: AtomicU64::new(0),
+    private_messages: AtomicU64::new(0),
+    bytes_received: AtomicU64::new(0),
+    bytes_sent: AtomicU64::new(0),
+    dropped_clients: AtomicU64::new(0),
+    flood_drops: AtomicU64::new(0),
+    timeouts: AtomicU64::new(0),
+    queued: AtomicU64::new(0),
+};
+
+pub fn count (counter: &AtomicU64, amount: u64) {
+    counter.fetch_add(amount, Ordering::Relaxed);
+}
+
+// only for gauges such as `queued`
+pub fn uncount (counter: &AtomicU64, amount: u64) {
+    counter.fetch_sub(amount, Ordering::Relaxed);
+}
+
+
+pub enum Kind {
+    Counter,
+    Gauge,
+}
+
+pub struct Sample {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+    pub value: u64,
+}
+
+// the gauges only the caller knows about come first, then the counters
+pub fn samples (clients: usize, connections: usize, rooms: usize) -> Vec<Sample> {
+    let sample = |name, help, kind, value| Sample { name, help, kind, value };
+    let counter = |name, help, counter: &AtomicU64| {
+        Sample { name, help, kind: Kind::Counter, value: counter.load(Ordering::Relaxed) }
+    };
+    vec![
+        sample("chat_clients", "Clients which have entered the chat", Kind::Gauge, clients as u64),
+        sample("chat_connections", "Open connections, including unregistered ones", Kind::Gauge,
+               connections as u64),
+        sample("chat_rooms", "Rooms with at least one client", Kind::Gauge, rooms as u64),
+        sample("chat_broadcast_queue_depth", "Events waiting for the broadcast thread", Kind::Gauge,
+               METRICS.queued.load(Ordering::Relaxed)),
+        counter("chat_connections_accepted_total", "Connections accepted", &METRICS.accepted),
+        counter("chat_connections_refused_total", "Connections refused by bans or limits", &METRICS.refused),
+        counter("chat_messages_total", "Messages sent to rooms", &METRICS.messages),
+        counter("chat_private_messages_total", "Private messages", &METRICS.private_messages),
+        counter("chat_received_bytes_total", "Bytes of input lines from clients", &METRICS.bytes_received),
+        counter("chat_sent_bytes_total", "Bytes written to clients", &METRICS.bytes_sent),
+        counter("chat_dropped_clients_total", "Clients dropped after a failed write", &METRICS.dropped_clients),
+        counter("chat_flood_drops_total", "Input lines dropped by flood control", &METRICS.flood_drops),
+        counter("chat_timeouts_total", "Clients disconnected for being idle", &METRICS.timeouts),
+    ]
+}
+
+// the Prometheus text exposition format
+pub fn render (samples: &[Sample]) -> String {
+    let mut text = String::new();
+    for sample in samples {
+        let kind = match sample.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        };
+        text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                               sample.name, sample.help, sample.name, kind, sample.name, sample.value));
+    }
+    text
+}
+
+
+// a minimal HTTP server answering GET /metrics, meant to be bound to a local address
+pub fn spawn_http_thread (listener: Listener, state: Shared) {
+    thread::spawn(move || {
+        loop {
+            if let Ok(stream) = listener.accept() {
+                let state = state.clone();
+                thread::spawn(move || respond(stream, state));
+            }
+        }
+    });
+}
+
+fn respond (mut stream: Stream, state: Shared) {
+    let request_line = match stream.try_clone().and_then(|reader| net::read_request(BufReader::new(reader))) {
+        Ok((request_line, _)) => request_line,
+        Err(_) => return,
+    };
+    let mut words = request_line.split_whitespace();
+    let response = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render(&state.lock().unwrap().stats());
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        },
+        (Some("GET"), Some(_)) => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        },
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    let _ = stream.write_all(response.as_bytes());
+}
@@ -0,0 +1,277 @@
+use std::io;
+use std::io::prelude::*;
+use std::fs;
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, TcpListener};
+use std::os::unix::net::{UnixStream, UnixListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use metrics;
+use metrics::METRICS;
+use tls::TlsStream;
+use tls::rustls::ServerConfig;
+
+
+// a peer which takes longer than this to accept a write is dropped
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// writes waiting in an outbox before its peer is considered too slow
+const OUTBOX_SIZE: usize = 1000;
+// the longest line a peer may send, room enough for an uploaded chunk of a file
+pub const MAX_LINE: usize = 64 * 1024;
+// the most header lines an HTTP request may have
+const MAX_HEADERS: usize = 100;
+
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+    Tls(TcpListener, Arc<ServerConfig>),
+}
+
+impl Listener {
+    pub fn bind_tcp (addr: SocketAddr) -> Result<Listener, String> {
+        TcpListener::bind(addr)
+            .map(Listener::Tcp)
+            .map_err(|e| format!("cannot listen on {}: {}", addr, e))
+    }
+
+    pub fn bind_tls (addr: SocketAddr, config: Arc<ServerConfig>) -> Result<Listener, String> {
+        TcpListener::bind(addr)
+            .map(|listener| Listener::Tls(listener, config))
+            .map_err(|e| format!("cannot listen on {}: {}", addr, e))
+    }
+
+    pub fn bind_unix (path: &Path) -> Result<Listener, String> {
+        remove_stale_socket(path);
+        UnixListener::bind(path)
+            .map(|listener| Listener::Unix(listener, path.to_path_buf()))
+            .map_err(|e| format!("cannot listen on {}: {}", path.display(), e))
+    }
+
+    pub fn accept (&self) -> io::Result<Stream> {
+        let stream = match *self {
+            Listener::Tcp(ref listener) => listener.accept().map(|(s, _)| Stream::Tcp(s))?,
+            Listener::Unix(ref listener, _) => listener.accept().map(|(s, _)| Stream::Unix(s))?,
+            Listener::Tls(ref listener, ref config) => {
+                let (socket, _) = listener.accept()?;
+                TlsStream::new(config.clone(), socket).map(Stream::Tls)?
+            },
+        };
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    pub fn name (&self) -> String {
+        match *self {
+            Listener::Tcp(ref listener) | Listener::Tls(ref listener, _) => {
+                listener.local_addr().unwrap().to_string()
+            },
+            Listener::Unix(_, ref path) => path.display().to_string(),
+        }
+    }
+}
+
+// a socket file left behind by a previous run would make bind() fail,
+// but only remove it when no server is answering on it any more
+fn remove_stale_socket (path: &Path) {
+    if path.exists() && UnixStream::connect(path).is_err() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+    Tls(TlsStream),
+}
+
+impl Stream {
+    pub fn try_clone (&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref s) => s.try_clone().map(Stream::Tcp),
+            Stream::Unix(ref s) => s.try_clone().map(Stream::Unix),
+            Stream::Tls(ref s) => s.try_clone().map(Stream::Tls),
+        }
+    }
+
+    // unix peers are usually unnamed, so they are told apart by connection id
+    pub fn peer_name (&self, id: usize) -> String {
+        match *self {
+            Stream::Tcp(ref s) => s.peer_addr().map(|a| a.to_string())
+                .unwrap_or_else(|_| format!("tcp:{}", id)),
+            Stream::Tls(ref s) => s.peer_addr().map(|a| a.to_string())
+                .unwrap_or_else(|_| format!("tls:{}", id)),
+            Stream::Unix(_) => format!("unix:{}", id),
+        }
+    }
+
+    // local unix peers have no address and are not counted per address
+    pub fn peer_ip (&self) -> Option<IpAddr> {
+        match *self {
+            Stream::Tcp(ref s) => s.peer_addr().ok().map(|a| a.ip()),
+            Stream::Tls(ref s) => s.peer_addr().ok().map(|a| a.ip()),
+            Stream::Unix(_) => None,
+        }
+    }
+
+    pub fn set_write_timeout (&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_write_timeout(timeout),
+            Stream::Unix(ref s) => s.set_write_timeout(timeout),
+            Stream::Tls(ref s) => s.set_write_timeout(timeout),
+        }
+    }
+
+    // also wakes up the thread blocked reading from another handle
+    pub fn shutdown (&self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.shutdown(Shutdown::Both),
+            Stream::Unix(ref s) => s.shutdown(Shutdown::Both),
+            Stream::Tls(ref s) => s.shutdown(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.read(buf),
+            Stream::Unix(ref mut s) => s.read(buf),
+            Stream::Tls(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.write(buf),
+            Stream::Unix(ref mut s) => s.write(buf),
+            Stream::Tls(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.flush(),
+            Stream::Unix(ref mut s) => s.flush(),
+            Stream::Tls(ref mut s) => s.flush(),
+        }
+    }
+}
+
+
+// Lines read like `BufRead::lines` does, except that a line longer than
+// `MAX_LINE` is an error rather than being buffered whole, however long it gets.
+pub struct Lines<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Lines<R> {
+    pub fn new (reader: R) -> Lines<R> {
+        Lines { reader }
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn next (&mut self) -> Option<io::Result<String>> {
+        let mut line = Vec::new();
+        match (&mut self.reader).take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => {},
+            Err(e) => return Some(Err(e)),
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        } else if line.len() > MAX_LINE {
+            return Some(Err(io::Error::new(io::ErrorKind::InvalidData, "line too long")));
+        }
+        Some(String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+}
+
+
+// The request line and headers of an HTTP request, up to the blank line after
+// them. Every line is bounded like any other, and so is the number of headers.
+pub fn read_request<R: BufRead> (reader: R) -> io::Result<(String, Vec<String>)> {
+    let mut lines = Lines::new(reader);
+    let mut next = || lines.next().unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+    let request_line = next()?;
+    let mut headers = Vec::new();
+    loop {
+        let line = next()?;
+        if line.is_empty() {
+            return Ok((request_line, headers));
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many headers"));
+        }
+        headers.push(line);
+    }
+}
+
+
+enum Outgoing {
+    Bytes(Vec<u8>),
+    Close,
+}
+
+// Writes to a stream made by a thread of its own, so a peer which stops reading
+// holds up nothing but its own queue. Once that is full the peer is dropped.
+#[derive(Clone)]
+pub struct Outbox {
+    queue: SyncSender<Outgoing>,
+}
+
+impl Outbox {
+    pub fn new (mut stream: Stream) -> Outbox {
+        let (queue, outgoing) = mpsc::sync_channel(OUTBOX_SIZE);
+        thread::spawn(move || {
+            // ends once everything queued is written and the owner is gone
+            for message in outgoing {
+                let bytes = match message {
+                    Outgoing::Bytes(bytes) => bytes,
+                    Outgoing::Close => {
+                        let _ = stream.shutdown();
+                        return;
+                    },
+                };
+                // the reader thread sees the stream end and cleans up as usual
+                if stream.write_all(&bytes).is_err() {
+                    metrics::count(&METRICS.dropped_clients, 1);
+                    let _ = stream.shutdown();
+                    return;
+                }
+                metrics::count(&METRICS.bytes_sent, bytes.len() as u64);
+            }
+        });
+        Outbox { queue }
+    }
+
+    // fails once the queue is full or the stream is gone
+    pub fn send (&self, bytes: Vec<u8>) -> io::Result<()> {
+        self.queue.try_send(Outgoing::Bytes(bytes)).map_err(|e| match e {
+            TrySendError::Full(_) => io::Error::new(io::ErrorKind::WouldBlock, "too many writes waiting"),
+            TrySendError::Disconnected(_) => io::Error::from(io::ErrorKind::BrokenPipe),
+        })
+    }
+
+    // waits for room in the queue, never to be called with the state locked
+    pub fn wait_send (&self, bytes: Vec<u8>) -> io::Result<()> {
+        self.queue.send(Outgoing::Bytes(bytes)).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    // shuts the stream down once everything queued before is written
+    pub fn close (&self) -> io::Result<()> {
+        self.queue.try_send(Outgoing::Close).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
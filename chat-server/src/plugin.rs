@@ -0,0 +1,78 @@
+use command;
+use command::Command;
+use config::Config;
+use event::Event;
+use server::State;
+use bots::{Dice, Reminders, LinkPreviews, WordFilter};
+
+
+// events emitted by plugins carry this id, 1 is for links and client ids start at 2
+pub const PLUGIN_ID: usize = 0;
+
+// what a plugin may do in response to an event or a tick
+pub enum Action {
+    // a message in `room`, under the plugin's name
+    Say { room: String, text: String },
+    // a private message to one client
+    Tell { to: String, text: String },
+    // a text command run with operator rights, e.g. `/mute alice 5m`
+    Command(String),
+}
+
+// A plugin lives in the broadcast thread and sees every event after it has
+// been delivered, except for those emitted by plugins, so bots cannot end up
+// talking to each other forever.
+pub trait Plugin: Send {
+    // also the name the plugin speaks under
+    fn name (&self) -> &str;
+
+    fn on_event (&mut self, event: &Event) -> Vec<Action>;
+
+    // called about once a second, for plugins acting on their own
+    fn on_tick (&mut self) -> Vec<Action> {
+        Vec::new()
+    }
+}
+
+pub fn load (config: &Config) -> Result<Vec<Box<dyn Plugin>>, String> {
+    config.plugins.iter().map(|name| -> Result<Box<dyn Plugin>, String> {
+        match name.as_str() {
+            "dice" => Ok(Box::new(Dice::new())),
+            "reminders" => Ok(Box::new(Reminders::new())),
+            "links" => match config.link_cache {
+                Some(ref path) => Ok(Box::new(LinkPreviews::open(path)?)),
+                None => Err("the links plugin needs a --link-cache".to_string()),
+            },
+            "filter" => match config.filter_words {
+                Some(ref path) => Ok(Box::new(WordFilter::open(path)?)),
+                None => Err("the filter plugin needs --filter-words".to_string()),
+            },
+            _ => Err(format!("unknown plugin `{}`", name)),
+        }
+    }).collect()
+}
+
+// turns an action into the event it stands for, commands take effect right away
+pub fn perform (state: &mut State, plugin: &str, action: Action) -> Option<Event> {
+    match action {
+        Action::Say { room, text } => {
+            Some(Event::Message { id: PLUGIN_ID, room, name: plugin.to_string(), text })
+        },
+        Action::Tell { to, text } => {
+            Some(Event::Private { id: PLUGIN_ID, from: plugin.to_string(), to, text })
+        },
+        Action::Command(line) => {
+            let result = match command::parse(&line) {
+                Some(Ok(Command::Kick(nick, reason))) => state.kick(plugin, &nick, &reason),
+                Some(Ok(Command::Mute(nick, duration))) => state.mute(plugin, &nick, duration),
+                Some(Ok(Command::Unmute(nick))) => state.unmute(&nick),
+                Some(Err(error)) => Err(error),
+                _ => Err("plugins may only /kick, /mute and /unmute".to_string()),
+            };
+            if let Err(error) = result {
+                log!(Warn, "plugin command failed", plugin = plugin, command = line, error = error);
+            }
+            None
+        },
+    }
+}
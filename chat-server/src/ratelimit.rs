@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+
+// forget earlier warnings after this long without flooding
+const WARNING_MEMORY: Duration = Duration::from_secs(60);
+
+// refills at `rate` tokens per second up to `capacity`
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new (rate: f64, capacity: f64) -> TokenBucket {
+        TokenBucket { rate, capacity, tokens: capacity, last: Instant::now() }
+    }
+
+    pub fn take (&mut self, amount: f64) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+
+pub enum Verdict {
+    Allow,
+    Warn,
+    Disconnect,
+}
+
+pub struct FloodLimits {
+    pub messages: f64,
+    pub bytes: f64,
+    pub burst: f64,
+    pub warnings: u32,
+}
+
+// the input allowance of one connection, limited both in lines and in bytes;
+// a rate of zero turns that limit off
+pub struct Limiter {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    max_warnings: u32,
+    warnings: u32,
+    last_warning: Instant,
+}
+
+impl Limiter {
+    pub fn new (limits: &FloodLimits) -> Limiter {
+        let bucket = |rate: f64| if rate > 0.0 {
+            Some(TokenBucket::new(rate, rate * limits.burst.max(1.0)))
+        } else {
+            None
+        };
+        Limiter {
+            messages: bucket(limits.messages),
+            bytes: bucket(limits.bytes),
+            max_warnings: limits.warnings,
+            warnings: 0,
+            last_warning: Instant::now(),
+        }
+    }
+
+    pub fn check (&mut self, length: usize) -> Verdict {
+        let message_ok = self.messages.as_mut().map(|b| b.take(1.0)).unwrap_or(true);
+        let bytes_ok = self.bytes.as_mut().map(|b| b.take(length as f64)).unwrap_or(true);
+        if message_ok && bytes_ok {
+            return Verdict::Allow;
+        }
+        if self.last_warning.elapsed() > WARNING_MEMORY {
+            self.warnings = 0;
+        }
+        self.last_warning = Instant::now();
+        self.warnings += 1;
+        if self.warnings > self.max_warnings {
+            Verdict::Disconnect
+        } else {
+            Verdict::Warn
+        }
+    }
+}
@@ -0,0 +1,1441 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use std::thread;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError};
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use std::collections::{HashMap, VecDeque};
+
+use accounts::{Account, Accounts};
+use bans::Bans;
+use command;
+use command::Command;
+use config::Config;
+use event::Event;
+use history::{History, Entry};
+use inbox;
+use inbox::{Inbox, Mail};
+use irc;
+use json;
+use link;
+use link::{Links, LINK_ID};
+use metrics;
+use metrics::{METRICS, Sample};
+use net::{Lines, Listener, Outbox, Stream};
+use plugin;
+use plugin::{Plugin, PLUGIN_ID};
+use ratelimit::{Limiter, Verdict};
+use transcript::Transcripts;
+use transfer;
+use transfer::{Download, Transfer, Transfers};
+use websocket;
+
+
+pub const DEFAULT_ROOM: &str = "lobby";
+
+// how often plugins get to act on their own
+const PLUGIN_TICK: Duration = Duration::from_secs(1);
+
+// how often the keepalive thread looks for idle clients
+const KEEPALIVE_CHECK: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Text,
+    Irc,
+    WebSocket,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Protocol::Text => "text",
+            Protocol::Irc => "irc",
+            Protocol::WebSocket => "websocket",
+        })
+    }
+}
+
+pub struct Client {
+    pub stream: Stream,
+    // everything sent to the client is written from here, outside the state lock
+    outbox: Outbox,
+    pub name: String,
+    pub rooms: Vec<String>,
+    pub protocol: Protocol,
+    // text and websocket clients may ask for JSON lines instead of plain text
+    json: bool,
+    // the registered nickname this client has logged in as
+    pub account: Option<String>,
+    address: Option<IpAddr>,
+    // granted with /op, configured operators are recognized by their account
+    operator: bool,
+    // Some(None) lasts until /unmute
+    muted: Option<Option<Instant>>,
+    // when the client last sent anything, and whether it has been pinged since
+    last_seen: Instant,
+    pinged: bool,
+    timed_out: bool,
+    // the client fell too far behind reading, the reader thread is about to clean up
+    dropped: bool,
+    // when the client last sent anything but an answer to a ping
+    active: Instant,
+    pub away: Option<String>,
+}
+
+impl Client {
+    pub fn new (stream: Stream, name: &str, protocol: Protocol) -> io::Result<Client> {
+        Ok(Client {
+            address: stream.peer_ip(),
+            outbox: Outbox::new(stream.try_clone()?),
+            stream,
+            name: name.to_string(),
+            rooms: Vec::new(),
+            protocol,
+            json: false,
+            account: None,
+            operator: false,
+            muted: None,
+            last_seen: Instant::now(),
+            pinged: false,
+            timed_out: false,
+            dropped: false,
+            active: Instant::now(),
+            away: None,
+        })
+    }
+
+    fn is_muted (&self) -> bool {
+        match self.muted {
+            Some(Some(until)) => Instant::now() < until,
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    fn encode (&self, message: &str) -> Vec<u8> {
+        match self.protocol {
+            Protocol::WebSocket => websocket::text_frames(message),
+            _ => message.as_bytes().to_vec(),
+        }
+    }
+
+    fn write (&mut self, message: &str) -> io::Result<()> {
+        let bytes = self.encode(message);
+        self.write_bytes(&bytes)
+    }
+
+    // a client whose outbox is full is dropped rather than waited for
+    fn write_bytes (&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.dropped {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        let result = self.outbox.send(bytes.to_vec());
+        if result.is_err() {
+            self.dropped = true;
+            let _ = self.stream.shutdown();
+            metrics::count(&METRICS.dropped_clients, 1);
+        }
+        result
+    }
+
+    fn deliver (&mut self, event: &Event) -> io::Result<()> {
+        let message = match self.protocol {
+            _ if self.json => Some(json::render(event)),
+            Protocol::Text | Protocol::WebSocket => Some(event.to_text()),
+            Protocol::Irc => irc::render(event, &self.name),
+        };
+        match message {
+            Some(message) => self.write(&message),
+            None => Ok(()),
+        }
+    }
+
+    // a message from the server itself, such as a command error
+    fn notice (&mut self, text: &str) {
+        let message = match self.protocol {
+            _ if self.json => json::notice(text),
+            Protocol::Text | Protocol::WebSocket => format!("{}\n", text),
+            Protocol::Irc => irc::notice(&self.name, text),
+        };
+        let _ = self.write(&message);
+    }
+
+    fn error (&mut self, text: &str) {
+        let message = match self.protocol {
+            _ if self.json => json::error(text),
+            Protocol::Text | Protocol::WebSocket => format!("error: {}\n", text),
+            Protocol::Irc => irc::notice(&self.name, text),
+        };
+        let _ = self.write(&message);
+    }
+
+    // websocket clients answer protocol pings by themselves, text clients
+    // are expected to reply with /pong, though any line will do
+    fn ping (&mut self, token: u64) {
+        let _ = match self.protocol {
+            Protocol::Text if self.json => self.write_bytes(json::ping(token).as_bytes()),
+            Protocol::Text => self.write_bytes(format!("PING {}\n", token).as_bytes()),
+            Protocol::Irc => self.write_bytes(format!("PING :{}\r\n", token).as_bytes()),
+            Protocol::WebSocket => self.write_bytes(&websocket::ping_frame(&token.to_string())),
+        };
+        self.pinged = true;
+    }
+
+    // the reader thread sees the connection end and cleans up as usual
+    fn close (&mut self, reason: &str) {
+        let message = match self.protocol {
+            _ if self.json => json::notice(reason),
+            Protocol::Text | Protocol::WebSocket => format!("{}\n", reason),
+            Protocol::Irc => format!("ERROR :Closing link: {}\r\n", reason),
+        };
+        if self.write(&message).is_ok() {
+            self.shutdown();
+        }
+    }
+
+    // once everything already queued has been written
+    fn shutdown (&mut self) {
+        if self.outbox.close().is_err() {
+            let _ = self.stream.shutdown();
+        }
+    }
+
+    fn download (&self, part: &Download) -> Vec<u8> {
+        let message = match self.protocol {
+            _ if self.json => json::download(part),
+            Protocol::Text | Protocol::WebSocket => part.to_text(),
+            Protocol::Irc => irc::notice(&self.name, part.to_text().trim_end()),
+        };
+        self.encode(&message)
+    }
+
+    fn entered (&mut self, room: &str, names: &[String], entries: &[Entry]) {
+        let message = match self.protocol {
+            _ if self.json => json::backlog(entries),
+            Protocol::Text | Protocol::WebSocket => text_backlog(room, entries),
+            Protocol::Irc => irc::entered(&self.name, room, names) + &irc::backlog(room, entries),
+        };
+        let _ = self.write(&message);
+    }
+
+    fn backlog (&mut self, room: &str, entries: &[Entry]) {
+        let message = match self.protocol {
+            _ if self.json => json::backlog(entries),
+            Protocol::Text | Protocol::WebSocket => text_backlog(room, entries),
+            Protocol::Irc => irc::backlog(room, entries),
+        };
+        let _ = self.write(&message);
+    }
+}
+
+// what became of a private message
+pub enum Delivery {
+    Sent,
+    // sent to someone who is away, with the reason they gave
+    Away(String),
+    // kept in the inbox of a registered nickname which is not connected
+    Queued,
+}
+
+// someone in a room as shown by /who
+pub struct Presence {
+    pub name: String,
+    pub away: Option<String>,
+    pub idle: Duration,
+}
+
+impl Presence {
+    pub fn to_text (&self) -> String {
+        match self.away {
+            Some(ref reason) => format!("{}, idle {}", away_message(&self.name, reason), describe_idle(self.idle)),
+            None => format!("{} is here, idle {}", self.name, describe_idle(self.idle)),
+        }
+    }
+}
+
+pub fn away_message (name: &str, reason: &str) -> String {
+    if reason.is_empty() {
+        format!("{} is away", name)
+    } else {
+        format!("{} is away: {}", name, reason)
+    }
+}
+
+pub fn queued_message (name: &str) -> String {
+    format!("{} is offline, the message will be delivered when they log in", name)
+}
+
+fn describe_idle (idle: Duration) -> String {
+    match idle.as_secs() {
+        seconds if seconds < 60 => format!("{}s", seconds),
+        seconds if seconds < 3600 => format!("{}m", seconds / 60),
+        seconds if seconds < 86400 => format!("{}h", seconds / 3600),
+        seconds => format!("{}d", seconds / 86400),
+    }
+}
+
+// whatever a listener let through, text is passed on line by line, so a line
+// break would let the sender make up lines from anyone else
+fn single_line (text: &str) -> Result<(), String> {
+    if text.contains(['\r', '\n']) {
+        Err("a message may not contain line breaks".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+fn text_backlog (room: &str, entries: &[Entry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let mut text = format!("-- last {} messages in {} --\n", entries.len(), room);
+    for entry in entries {
+        text.push_str(&entry.to_text());
+    }
+    text.push_str("--\n");
+    text
+}
+
+
+pub struct State {
+    pub config: Arc<Config>,
+    pub clients: HashMap<usize, Client>,
+    pub history: History,
+    pub accounts: Accounts,
+    pub bans: Bans,
+    pub inbox: Inbox,
+    pub transfers: Transfers,
+    pub links: Links,
+    transcripts: Option<Transcripts>,
+    // open connections, including those which have not registered yet
+    connections: usize,
+    connections_per_ip: HashMap<IpAddr, usize>,
+    // set once the server starts shutting down, new connections are refused
+    closing: bool,
+}
+
+pub type Shared = Arc<Mutex<State>>;
+
+impl State {
+    pub fn new (config: Arc<Config>, history: History, accounts: Accounts, bans: Bans, inbox: Inbox,
+                transfers: Transfers) -> State {
+        State {
+            transcripts: config.transcript_dir.clone().map(Transcripts::new),
+            links: Links::new(&config.server_name),
+            config,
+            clients: HashMap::new(),
+            history,
+            accounts,
+            bans,
+            inbox,
+            transfers,
+            connections: 0,
+            connections_per_ip: HashMap::new(),
+            closing: false,
+        }
+    }
+
+    fn admit (&mut self, ip: Option<IpAddr>) -> Result<(), String> {
+        if self.closing {
+            return Err("the server is shutting down".to_string());
+        }
+        if let Some(ban) = ip.and_then(|ip| self.bans.find(ip)) {
+            return match ban.reason.as_str() {
+                "" => Err("you are banned from this server".to_string()),
+                reason => Err(format!("you are banned from this server: {}", reason)),
+            };
+        }
+        let max = self.config.max_clients;
+        if max > 0 && self.connections >= max {
+            return Err("the server is full".to_string());
+        }
+        if let Some(ip) = ip {
+            let max = self.config.max_clients_per_ip;
+            let count = self.connections_per_ip.entry(ip).or_insert(0);
+            if max > 0 && *count >= max {
+                return Err(format!("too many connections from {}", ip));
+            }
+            *count += 1;
+        }
+        self.connections += 1;
+        Ok(())
+    }
+
+    fn release (&mut self, ip: Option<IpAddr>) {
+        self.connections -= 1;
+        if let Some(ip) = ip {
+            if let Some(count) = self.connections_per_ip.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    self.connections_per_ip.remove(&ip);
+                }
+            }
+        }
+    }
+
+    // writes are queued under the state lock so they never interleave with a broadcast
+    pub fn send_to (&mut self, id: usize, bytes: &[u8]) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            let _ = client.write_bytes(bytes);
+        }
+    }
+
+    pub fn notice (&mut self, id: usize, text: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.notice(text);
+        }
+    }
+
+    pub fn disconnect (&mut self, id: usize, reason: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.close(reason);
+        }
+    }
+
+    pub fn touch (&mut self, id: usize) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.last_seen = Instant::now();
+            client.pinged = false;
+        }
+    }
+
+    // pings clients which have been quiet for `ping_interval`
+    // and drops those which stayed quiet for `idle_timeout`
+    fn keep_alive (&mut self, token: u64) {
+        let (ping_interval, idle_timeout) = (self.config.ping_interval, self.config.idle_timeout);
+        let mut timed_out = Vec::new();
+        for (&id, client) in self.clients.iter_mut() {
+            let idle = client.last_seen.elapsed();
+            if idle_timeout > Duration::from_secs(0) && idle >= idle_timeout {
+                timed_out.push(id);
+            } else if ping_interval > Duration::from_secs(0) && idle >= ping_interval && !client.pinged {
+                log!(Debug, "pinging idle client", id = id, name = client.name);
+                client.ping(token);
+            }
+        }
+        for id in timed_out {
+            if let Some(client) = self.clients.get_mut(&id) {
+                log!(Info, "client timed out", id = id, name = client.name);
+                metrics::count(&METRICS.timeouts, 1);
+                client.timed_out = true;
+                client.close("timed out");
+            }
+        }
+    }
+
+    // moderation on behalf of `by`, an operator or a plugin
+    pub fn kick (&mut self, by: &str, nick: &str, reason: &str) -> Result<(), String> {
+        let id = self.find(nick).ok_or(format!("no such nickname {}", nick))?;
+        log!(Info, "kicked", by = by, name = nick);
+        self.disconnect(id, &with_reason(&format!("kicked by {}", by), reason));
+        Ok(())
+    }
+
+    pub fn mute (&mut self, by: &str, nick: &str, duration: Option<Duration>) -> Result<(), String> {
+        let id = self.find(nick).ok_or(format!("no such nickname {}", nick))?;
+        let until = match duration {
+            Some(duration) => Some(Instant::now().checked_add(duration).ok_or("duration too long")?),
+            None => None,
+        };
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.muted = Some(until);
+            client.notice(&format!("you have been muted by {}", by));
+        }
+        Ok(())
+    }
+
+    pub fn unmute (&mut self, nick: &str) -> Result<(), String> {
+        let id = self.find(nick).ok_or(format!("no such nickname {}", nick))?;
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.muted = None;
+            client.notice("you are no longer muted");
+        }
+        Ok(())
+    }
+
+    fn is_operator (&self, id: usize) -> bool {
+        match self.clients.get(&id) {
+            Some(client) => client.operator || client.account.as_ref()
+                .map(|account| self.config.operators.contains(account))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    pub fn broadcast (&mut self, event: &Event) {
+        for client in self.clients.values_mut() {
+            // the reader thread of a dropped client sees the stream end and cleans up
+            // as usual, so its departure is announced here and on linked servers
+            if event.is_for(&client.name, &client.rooms) {
+                let _ = client.deliver(event);
+            }
+        }
+    }
+
+    // tells both sides once a file has been uploaded completely
+    fn offered (&mut self, transfer: &Transfer) {
+        if let Some(id) = self.find(&transfer.from) {
+            self.notice(id, &format!("sent {} to {}, waiting for them to accept", transfer.name, transfer.to));
+        }
+        if let Some(id) = self.find(&transfer.to) {
+            self.notice(id, &format!("{} offers you {} ({} bytes), /accept {} or /decline {}",
+                                     transfer.from, transfer.name, transfer.size, transfer.id, transfer.id));
+        }
+    }
+
+    // @nick mentions of registered nicknames are kept in their inbox
+    fn record_mentions (&mut self, room: &str, from: &str, text: &str) {
+        for nick in inbox::mentioned(text) {
+            if nick != from && self.accounts.is_registered(&nick) {
+                if let Err(error) = self.inbox.add(Mail::new(&nick, Some(room), from, text)) {
+                    log!(Error, "cannot record mention", name = nick, error = error);
+                }
+            }
+        }
+    }
+
+    pub fn stats (&self) -> Vec<Sample> {
+        let mut rooms: Vec<&String> = self.clients.values().flat_map(|c| c.rooms.iter()).collect();
+        rooms.sort();
+        rooms.dedup();
+        metrics::samples(self.clients.len(), self.connections, rooms.len())
+    }
+
+    // plugins speak under their own name, which no client may take,
+    // and neither may they take the name of someone on a linked server
+    pub fn is_taken (&self, name: &str) -> bool {
+        self.find(name).is_some() || self.config.plugins.iter().any(|plugin| plugin == name) ||
+            self.links.find(name).is_some()
+    }
+
+    pub fn find (&self, name: &str) -> Option<usize> {
+        self.clients.iter().find(|c| c.1.name == name).map(|c| *c.0)
+    }
+
+    pub fn who (&self, room: &str) -> Vec<Presence> {
+        let mut people: Vec<Presence> = self.clients.values()
+            .filter(|c| c.rooms.iter().any(|r| r == room))
+            .map(|c| Presence { name: c.name.clone(), away: c.away.clone(), idle: c.active.elapsed() })
+            .chain(self.links.users.iter()
+                   .filter(|u| u.rooms.iter().any(|r| r == room))
+                   .map(|u| Presence { name: u.name.clone(), away: u.away.clone(), idle: u.active.elapsed() }))
+            .collect();
+        people.sort_by(|a, b| a.name.cmp(&b.name));
+        people
+    }
+
+    pub fn names (&self, room: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.clients.values()
+            .filter(|c| c.rooms.iter().any(|r| r == room))
+            .map(|c| c.name.clone())
+            .chain(self.links.users.iter().filter(|u| u.rooms.iter().any(|r| r == room)).map(|u| u.name.clone()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    // entering a room and receiving its backlog happen under one lock,
+    // so no message is either missed or delivered twice
+    fn enter_room (&mut self, id: usize, room: &str) -> bool {
+        match self.clients.get_mut(&id) {
+            Some(ref client) if client.rooms.iter().any(|r| r == room) => return false,
+            Some(client) => client.rooms.push(room.to_string()),
+            None => return false,
+        }
+        let names = self.names(room);
+        let entries = self.history.recent(room, self.history.capacity());
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.entered(room, &names, &entries);
+        }
+        true
+    }
+
+    fn leave_room (&mut self, id: usize, room: &str) -> bool {
+        match self.clients.get_mut(&id) {
+            Some(client) => {
+                let count = client.rooms.len();
+                client.rooms.retain(|r| r != room);
+                client.rooms.len() != count
+            },
+            None => false,
+        }
+    }
+}
+
+
+// the operations a connection can perform, whichever protocol it speaks
+pub struct Session {
+    pub id: usize,
+    pub name: String,
+    state: Shared,
+    tx: Sender<Event>,
+    limiter: Limiter,
+}
+
+impl Session {
+    pub fn new (id: usize, name: &str, state: Shared, tx: Sender<Event>) -> Session {
+        let limiter = Limiter::new(&state.lock().unwrap().config.flood);
+        Session {
+            id,
+            name: name.to_string(),
+            state,
+            tx,
+            limiter,
+        }
+    }
+
+    // counts a line against the flood limits; the caller drops the line
+    // unless it is allowed and hangs up on `Disconnect`
+    pub fn throttle (&mut self, line: &str) -> Verdict {
+        metrics::count(&METRICS.bytes_received, line.len() as u64);
+        // file data is limited by --max-file-size rather than the byte rate
+        let length = if line.starts_with("/chunk ") { 0 } else { line.len() };
+        let verdict = self.limiter.check(length);
+        match verdict {
+            Verdict::Allow => {},
+            Verdict::Warn => {
+                metrics::count(&METRICS.flood_drops, 1);
+                self.notice("you are sending too fast, slow down");
+            },
+            Verdict::Disconnect => {
+                metrics::count(&METRICS.flood_drops, 1);
+                log!(Warn, "disconnected for flooding", id = self.id, name = self.name);
+                self.notice("disconnected for flooding");
+            },
+        }
+        verdict
+    }
+
+    pub fn register (&mut self, client: Client) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.is_taken(&client.name) {
+            return Err(format!("nickname {} is already in use", client.name));
+        }
+        if state.accounts.is_registered(&client.name) && client.account.as_ref() != Some(&client.name) {
+            return Err(format!("nickname {} is registered", client.name));
+        }
+        self.name = client.name.clone();
+        state.clients.insert(self.id, client);
+        Ok(())
+    }
+
+    pub fn is_registered (&self, nick: &str) -> bool {
+        self.state.lock().unwrap().accounts.is_registered(nick)
+    }
+
+    // checks a password without logging in, the hashing happens outside the lock
+    pub fn authenticate (&self, nick: &str, password: &str) -> Result<(), String> {
+        let account = self.state.lock().unwrap().accounts.get(nick).cloned();
+        match account {
+            Some(ref account) if account.verify(password) => Ok(()),
+            Some(_) => Err("wrong password".to_string()),
+            None => Err(format!("nickname {} is not registered", nick)),
+        }
+    }
+
+    pub fn register_account (&self, password: &str) -> Result<(), String> {
+        if !is_valid_nick(&self.name) {
+            return Err("choose a nickname with /nick before registering".to_string());
+        }
+        {
+            let state = self.state.lock().unwrap();
+            if !state.accounts.is_enabled() {
+                return Err("registration is disabled on this server".to_string());
+            }
+            if state.accounts.is_registered(&self.name) {
+                return Err(format!("nickname {} is already registered", self.name));
+            }
+        }
+        let account = Account::create(&self.name, password);
+        let mut state = self.state.lock().unwrap();
+        state.accounts.add(account)?;
+        if let Some(client) = state.clients.get_mut(&self.id) {
+            client.account = Some(self.name.clone());
+        }
+        Ok(())
+    }
+
+    // the nickname is taken over and the account set under one lock, so
+    // nobody can take the nickname in between and a failed rename leaves
+    // the client logged out
+    pub fn login (&mut self, nick: &str, password: &str) -> Result<(), String> {
+        self.authenticate(nick, password)?;
+        let renamed = {
+            let mut state = self.state.lock().unwrap();
+            match state.find(nick) {
+                Some(id) if id != self.id => return Err(format!("{} is already logged in", nick)),
+                _ => {},
+            }
+            let renamed = if self.name != nick { Some(self.take_name(&mut state, nick, Some(nick))?) } else { None };
+            if let Some(client) = state.clients.get_mut(&self.id) {
+                client.account = Some(nick.to_string());
+            }
+            renamed
+        };
+        if let Some(rooms) = renamed {
+            self.renamed(nick, rooms);
+        }
+        self.open_inbox();
+        Ok(())
+    }
+
+    // once logged in, the private messages which came in the meantime are
+    // handed over and mentions are pointed out
+    pub fn open_inbox (&self) {
+        let mut state = self.state.lock().unwrap();
+        let logged_in = state.clients.get(&self.id).and_then(|c| c.account.as_ref()) == Some(&self.name);
+        if !logged_in {
+            return;
+        }
+        let messages = state.inbox.take_messages(&self.name);
+        let mentions = state.inbox.mentions(&self.name).len();
+        if let Some(client) = state.clients.get_mut(&self.id) {
+            for mail in messages {
+                client.notice(&mail.to_text());
+            }
+            if mentions > 0 {
+                let plural = if mentions == 1 { "" } else { "s" };
+                client.notice(&format!("{} mention{} in your inbox, see /inbox", mentions, plural));
+            }
+        }
+    }
+
+    pub fn inbox (&self) -> Result<Vec<Mail>, String> {
+        let account = self.account()?;
+        Ok(self.state.lock().unwrap().inbox.mentions(&account))
+    }
+
+    pub fn clear_inbox (&self) -> Result<(), String> {
+        let account = self.account()?;
+        self.state.lock().unwrap().inbox.clear_mentions(&account)
+    }
+
+    fn account (&self) -> Result<String, String> {
+        let state = self.state.lock().unwrap();
+        state.clients.get(&self.id).and_then(|c| c.account.clone())
+            .ok_or("you need to /register or /login to have an inbox".to_string())
+    }
+
+    // with require-auth set, only clients who have logged in may talk
+    pub fn may_speak (&self) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        let logged_in = state.clients.get(&self.id).map(|c| c.account.is_some()).unwrap_or(false);
+        if state.config.require_auth && !logged_in {
+            Err("you need to /register or /login before talking".to_string())
+        } else if state.clients.get(&self.id).map(Client::is_muted).unwrap_or(false) {
+            Err("you are muted".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    // the moderation commands below are only for operators
+    fn moderate (&self) -> Result<MutexGuard<'_, State>, String> {
+        let state = self.state.lock().unwrap();
+        if state.is_operator(self.id) {
+            Ok(state)
+        } else {
+            Err("you are not an operator".to_string())
+        }
+    }
+
+    pub fn kick (&self, nick: &str, reason: &str) -> Result<(), String> {
+        self.moderate()?.kick(&self.name, nick, reason)
+    }
+
+    // a nickname bans the address that client is connected from,
+    // everyone connected from a banned address is disconnected
+    pub fn ban (&self, target: &str, duration: Option<Duration>, reason: &str) -> Result<IpAddr, String> {
+        let mut state = self.moderate()?;
+        let address = match target.parse() {
+            Ok(address) => address,
+            Err(_) => {
+                let id = state.find(target).ok_or(format!("no such nickname or address {}", target))?;
+                state.clients[&id].address.ok_or(format!("{} has no address to ban", target))?
+            },
+        };
+        state.bans.add(address, duration, reason)?;
+        log!(Info, "banned", by = self.name, addr = address);
+        let banned: Vec<usize> = state.clients.iter()
+            .filter(|c| c.1.address == Some(address))
+            .map(|c| *c.0)
+            .collect();
+        for id in banned {
+            state.disconnect(id, &with_reason(&format!("banned by {}", self.name), reason));
+        }
+        Ok(address)
+    }
+
+    pub fn unban (&self, address: &str) -> Result<(), String> {
+        let mut state = self.moderate()?;
+        let address = address.parse().map_err(|_| format!("invalid address {}", address))?;
+        if state.bans.remove(address)? {
+            Ok(())
+        } else {
+            Err(format!("{} is not banned", address))
+        }
+    }
+
+    pub fn mute (&self, nick: &str, duration: Option<Duration>) -> Result<(), String> {
+        self.moderate()?.mute(&self.name, nick, duration)
+    }
+
+    pub fn unmute (&self, nick: &str) -> Result<(), String> {
+        self.moderate()?.unmute(nick)
+    }
+
+    pub fn stats (&self) -> Result<Vec<Sample>, String> {
+        Ok(self.moderate()?.stats())
+    }
+
+    pub fn op (&self, nick: &str) -> Result<(), String> {
+        let mut state = self.moderate()?;
+        let id = state.find(nick).ok_or(format!("no such nickname {}", nick))?;
+        if let Some(client) = state.clients.get_mut(&id) {
+            client.operator = true;
+            client.notice(&format!("{} made you an operator", self.name));
+        }
+        Ok(())
+    }
+
+    // offers a file of `size` bytes to `to`, to be uploaded in chunks
+    pub fn send_file (&self, to: &str, path: &str, size: u64) -> Result<Transfer, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.find(to).is_none() {
+            return Err(format!("no such nickname {}", to));
+        }
+        let transfer = state.transfers.offer(&self.name, to, path, size)?;
+        if transfer.is_complete() {
+            state.offered(&transfer);
+        }
+        Ok(transfer)
+    }
+
+    pub fn upload (&self, id: u64, offset: u64, data: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(transfer) = state.transfers.append(&self.name, id, offset, data)? {
+            state.offered(&transfer);
+        }
+        Ok(())
+    }
+
+    pub fn accept (&self, id: u64) -> Result<(), String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let transfer = state.transfers.accept(&self.name, id)?;
+            if let Some(sender) = state.find(&transfer.from) {
+                state.notice(sender, &format!("{} accepted {}", self.name, transfer.name));
+            }
+        }
+        self.download(id)
+    }
+
+    pub fn decline (&self, id: u64) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state.transfers.decline(&self.name, id)?;
+        if let Some(sender) = state.find(&transfer.from) {
+            state.notice(sender, &format!("{} declined {}", self.name, transfer.name));
+        }
+        Ok(())
+    }
+
+    // sends the file in chunks, taking the lock for one chunk at a time
+    // so a large file does not hold up everyone else
+    pub fn download (&self, id: u64) -> Result<(), String> {
+        let (transfer, mut file) = self.state.lock().unwrap().transfers.download(&self.name, id)?;
+        self.download_part(&Download::Start { id, size: transfer.size, name: &transfer.name })?;
+        let mut buffer = vec![0; transfer::CHUNK_SIZE];
+        let mut offset = 0;
+        loop {
+            let length = file.read(&mut buffer).map_err(|e| format!("cannot read {}: {}", transfer.name, e))?;
+            if length == 0 {
+                break;
+            }
+            self.download_part(&Download::Chunk { id, offset, data: transfer::encode(&buffer[..length]) })?;
+            offset += length as u64;
+        }
+        self.download_part(&Download::End { id })
+    }
+
+    // waits for room in the outbox without the lock, a slow download holds up nobody else
+    fn download_part (&self, part: &Download) -> Result<(), String> {
+        let (outbox, bytes) = match self.state.lock().unwrap().clients.get(&self.id) {
+            Some(client) => (client.outbox.clone(), client.download(part)),
+            None => return Err("disconnected".to_string()),
+        };
+        outbox.wait_send(bytes).map_err(|e| e.to_string())
+    }
+
+    pub fn rooms (&self) -> Vec<String> {
+        match self.state.lock().unwrap().clients.get(&self.id) {
+            Some(client) => client.rooms.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn names (&self, room: &str) -> Vec<String> {
+        self.state.lock().unwrap().names(room)
+    }
+
+    pub fn connect (&self, room: &str) {
+        if self.state.lock().unwrap().enter_room(self.id, room) {
+            self.send(Event::Connected { id: self.id, room: room.to_string(), name: self.name.clone() });
+        }
+    }
+
+    pub fn join (&self, room: &str) -> bool {
+        let entered = self.state.lock().unwrap().enter_room(self.id, room);
+        if entered {
+            self.send(Event::Joined { id: self.id, room: room.to_string(), name: self.name.clone() });
+        }
+        entered
+    }
+
+    pub fn part (&self, room: &str) -> bool {
+        let left = self.state.lock().unwrap().leave_room(self.id, room);
+        if left {
+            self.send(Event::Left { id: self.id, room: room.to_string(), name: self.name.clone() });
+        }
+        left
+    }
+
+    pub fn say (&self, room: &str, text: &str) -> Result<(), String> {
+        self.may_speak()?;
+        single_line(text)?;
+        self.send(Event::Message {
+            id: self.id,
+            room: room.to_string(),
+            name: self.name.clone(),
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn whisper (&self, to: &str, text: &str) -> Result<Delivery, String> {
+        self.may_speak()?;
+        single_line(text)?;
+        let away = {
+            let mut state = self.state.lock().unwrap();
+            // plugins can be messaged too, though they are not clients
+            if !state.is_taken(to) {
+                if !state.accounts.is_registered(to) {
+                    return Err(format!("no such nickname {}", to));
+                }
+                state.inbox.add(Mail::new(to, None, &self.name, text))?;
+                return Ok(Delivery::Queued);
+            }
+            match state.find(to) {
+                Some(id) => state.clients[&id].away.clone(),
+                None => state.links.find(to).and_then(|user| user.away.clone()),
+            }
+        };
+        self.send(Event::Private {
+            id: self.id,
+            from: self.name.clone(),
+            to: to.to_string(),
+            text: text.to_string(),
+        });
+        Ok(away.map(Delivery::Away).unwrap_or(Delivery::Sent))
+    }
+
+    pub fn away (&self, reason: &str) -> Result<(), String> {
+        single_line(reason)?;
+        let mut state = self.state.lock().unwrap();
+        if let Some(client) = state.clients.get_mut(&self.id) {
+            client.away = Some(reason.to_string());
+        }
+        link::away(&mut state, &self.name, Some(reason));
+        Ok(())
+    }
+
+    pub fn back (&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        match state.clients.get_mut(&self.id) {
+            Some(client) if client.away.is_some() => client.away = None,
+            _ => return Err("you are not away".to_string()),
+        }
+        link::away(&mut state, &self.name, None);
+        Ok(())
+    }
+
+    pub fn who (&self, room: &str) -> Vec<Presence> {
+        self.state.lock().unwrap().who(room)
+    }
+
+    pub fn rename (&mut self, new: &str) -> Result<(), String> {
+        let rooms = {
+            let mut state = self.state.lock().unwrap();
+            let account = state.clients.get(&self.id).and_then(|c| c.account.clone());
+            self.take_name(&mut state, new, account.as_deref())?
+        };
+        self.renamed(new, rooms);
+        Ok(())
+    }
+
+    // gives the client the nickname `new` if it is free and, when registered,
+    // belongs to `account`; returns the rooms to announce the change in
+    fn take_name (&self, state: &mut State, new: &str, account: Option<&str>) -> Result<Vec<String>, String> {
+        if !is_valid_nick(new) {
+            return Err(format!("invalid nickname {}", new));
+        }
+        if state.is_taken(new) {
+            return Err(format!("nickname {} is already in use", new));
+        }
+        if state.accounts.is_registered(new) && account != Some(new) {
+            return Err(format!("nickname {} is registered, use /login", new));
+        }
+        let rooms = match state.clients.get_mut(&self.id) {
+            Some(client) => {
+                client.name = new.to_string();
+                client.rooms.clone()
+            },
+            None => Vec::new(),
+        };
+        state.transfers.rename(&self.name, new);
+        Ok(rooms)
+    }
+
+    // announces a nickname taken with take_name
+    fn renamed (&mut self, new: &str, rooms: Vec<String>) {
+        let old = self.name.clone();
+        log!(Debug, "nickname changed", id = self.id, name = old, new = new);
+        self.name = new.to_string();
+        self.send(Event::Nick { id: self.id, rooms, old, new: new.to_string() });
+    }
+
+    pub fn replay (&self, room: &str, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        let entries = state.history.recent(room, count);
+        if let Some(client) = state.clients.get_mut(&self.id) {
+            client.backlog(room, &entries);
+        }
+    }
+
+    // every line from the client shows that it is still there
+    pub fn touch (&self) {
+        self.state.lock().unwrap().touch(self.id);
+    }
+
+    // anything but an answer to a ping shows that someone is at the keyboard
+    pub fn active (&self) {
+        if let Some(client) = self.state.lock().unwrap().clients.get_mut(&self.id) {
+            client.active = Instant::now();
+        }
+    }
+
+    pub fn notice (&self, text: &str) {
+        self.state.lock().unwrap().notice(self.id, text);
+    }
+
+    pub fn error (&self, text: &str) {
+        if let Some(client) = self.state.lock().unwrap().clients.get_mut(&self.id) {
+            client.error(text);
+        }
+    }
+
+    pub fn set_json (&self, json: bool) {
+        if let Some(client) = self.state.lock().unwrap().clients.get_mut(&self.id) {
+            client.json = json;
+        }
+    }
+
+    pub fn send_raw (&self, text: &str) {
+        self.state.lock().unwrap().send_to(self.id, text.as_bytes());
+    }
+
+    pub fn close (self) {
+        let client = {
+            let mut state = self.state.lock().unwrap();
+            state.transfers.abandon(&self.name);
+            state.clients.remove(&self.id)
+        };
+        // sent even without rooms, linked servers know about everyone
+        if let Some(client) = client {
+            let reason = if client.timed_out { "timed out" } else { "disconnected" };
+            self.send(Event::Disconnected {
+                id: self.id,
+                rooms: client.rooms,
+                name: self.name.clone(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    fn send (&self, event: Event) {
+        metrics::count(&METRICS.queued, 1);
+        self.tx.send(event).unwrap();
+    }
+}
+
+fn with_reason (message: &str, reason: &str) -> String {
+    if reason.is_empty() {
+        message.to_string()
+    } else {
+        format!("{}: {}", message, reason)
+    }
+}
+
+// what a nickname may hold besides letters and digits, as in IRC
+pub const NICK_SPECIAL: &str = "-_[]{}\\|^`";
+
+pub fn is_valid_nick (nick: &str) -> bool {
+    !nick.is_empty() && nick.len() <= 30 &&
+        !nick.starts_with(|c: char| c.is_ascii_digit() || c == '-') &&
+        nick.chars().all(|c| c.is_ascii_alphanumeric() || NICK_SPECIAL.contains(c))
+}
+
+pub fn normalize_room (room: &str) -> Option<String> {
+    let room = room.trim_start_matches('#');
+    if room.is_empty() || room.len() > 50 || room.contains(|c: char| c.is_whitespace() || c == ',') {
+        None
+    } else {
+        Some(room.to_string())
+    }
+}
+
+
+pub fn spawn_accept_thread (listener: Listener, protocol: Protocol, next_id: Arc<AtomicUsize>,
+                            state: Shared, tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    let id = next_id.fetch_add(1, Ordering::SeqCst);
+                    let ip = stream.peer_ip();
+                    let admitted = state.lock().unwrap().admit(ip);
+                    if let Err(reason) = admitted {
+                        metrics::count(&METRICS.refused, 1);
+                        log!(Info, "connection refused", id = id, addr = stream.peer_name(id),
+                             reason = reason);
+                        refuse(stream, protocol, &reason);
+                        continue;
+                    }
+                    metrics::count(&METRICS.accepted, 1);
+                    let admission = Admission { state: state.clone(), ip };
+                    let (state, tx) = (state.clone(), tx.clone());
+                    thread::spawn(move || {
+                        match protocol {
+                            Protocol::Text => serve_text(id, stream, state, tx),
+                            Protocol::Irc => irc::serve(id, stream, state, tx),
+                            Protocol::WebSocket => websocket::serve(id, stream, state, tx),
+                        }
+                        drop(admission);
+                    });
+                }
+                Err(_) => { /* connection failed */ }
+            }
+        }
+    });
+}
+
+// gives the connection slot back however the client thread ends
+struct Admission {
+    state: Shared,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for Admission {
+    fn drop (&mut self) {
+        self.state.lock().unwrap().release(self.ip);
+    }
+}
+
+// websocket clients are refused before the handshake, so they only see the close
+fn refuse (mut stream: Stream, protocol: Protocol, reason: &str) {
+    let message = match protocol {
+        Protocol::Text => format!("error: {}\n", reason),
+        Protocol::Irc => format!("ERROR :Closing link: {}\r\n", reason),
+        Protocol::WebSocket => return,
+    };
+    let _ = stream.write_all(message.as_bytes());
+}
+
+fn serve_text (id: usize, stream: Stream, state: Shared, tx: Sender<Event>) {
+    let reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(_) => return,
+    };
+    // an overlong line ends the session like a closed connection
+    let lines = Lines::new(reader).map_while(Result::ok);
+    run_text_session(Session::new(id, "", state, tx), stream, Protocol::Text, lines);
+}
+
+// the plain-text protocol, for raw sockets as well as websocket messages
+pub fn run_text_session<I: Iterator<Item=String>> (mut session: Session, stream: Stream,
+                                                   protocol: Protocol, lines: I) {
+    let name = stream.peer_name(session.id);
+    let client = match Client::new(stream, &name, protocol) {
+        Ok(client) => client,
+        Err(_) => return,
+    };
+    if session.register(client).is_err() {
+        return;
+    }
+    let mut room = DEFAULT_ROOM.to_string();
+    let mut json = false;
+
+    log!(Info, "client connected", id = session.id, addr = name, protocol = protocol);
+    session.connect(&room);
+
+    for line in lines {
+        session.touch();
+        match session.throttle(&line) {
+            Verdict::Allow => {},
+            Verdict::Warn => continue,
+            Verdict::Disconnect => break,
+        }
+        let line = line.trim_end_matches('\r');
+        let parsed = if json && !line.is_empty() { Some(json::parse(line)) } else { command::parse(line) };
+        match parsed {
+            Some(Ok(Command::Pong)) => {},
+            _ => session.active(),
+        }
+        match parsed {
+            Some(Ok(Command::Join(new_room))) => {
+                if new_room != room {
+                    session.part(&room);
+                    session.join(&new_room);
+                    room = new_room;
+                }
+            },
+            Some(Ok(Command::History(count))) => session.replay(&room, count),
+            Some(Ok(Command::Pong)) => {},
+            Some(Ok(Command::Json(on))) => {
+                json = on;
+                session.set_json(on);
+                session.notice(if on { "switched to the json protocol" } else { "switched to plain text" });
+            },
+            Some(Ok(Command::Say(text))) => {
+                if let Err(error) = session.say(&room, &text) {
+                    session.error(&error);
+                }
+            },
+            Some(Ok(Command::Names)) => {
+                session.notice(&format!("names in {}: {}", room, session.names(&room).join(" ")))
+            },
+            Some(Ok(Command::Nick(nick))) => {
+                if let Err(error) = session.rename(&nick) {
+                    session.error(&error);
+                }
+            },
+            Some(Ok(Command::Msg(to, text))) => match session.whisper(&to, &text) {
+                Ok(Delivery::Sent) => {},
+                Ok(Delivery::Away(reason)) => session.notice(&away_message(&to, &reason)),
+                Ok(Delivery::Queued) => session.notice(&queued_message(&to)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Away(reason))) => match session.away(&reason) {
+                Ok(()) => session.notice("you are marked as away, /back when you return"),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Back)) => match session.back() {
+                Ok(()) => session.notice("welcome back"),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Who(target))) => {
+                let target = target.unwrap_or_else(|| room.clone());
+                let people = session.who(&target);
+                session.notice(&format!("who in {}:", target));
+                for presence in people {
+                    session.notice(&presence.to_text());
+                }
+            },
+            Some(Ok(Command::Register(password))) => match session.register_account(&password) {
+                Ok(()) => session.notice(&format!("registered and logged in as {}", session.name)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Login(nick, password))) => match session.login(&nick, &password) {
+                Ok(()) => session.notice(&format!("logged in as {}", session.name)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Inbox)) => match session.inbox() {
+                Ok(ref mentions) if mentions.is_empty() => session.notice("your inbox is empty"),
+                Ok(mentions) => {
+                    session.notice("inbox:");
+                    for mail in mentions {
+                        session.notice(&mail.to_text());
+                    }
+                },
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::ClearInbox)) => match session.clear_inbox() {
+                Ok(()) => session.notice("inbox cleared"),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Kick(nick, reason))) => match session.kick(&nick, &reason) {
+                Ok(()) => session.notice(&format!("kicked {}", nick)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Ban(target, duration, reason))) => {
+                match session.ban(&target, duration, &reason) {
+                    Ok(address) => session.notice(&format!("banned {}", address)),
+                    Err(error) => session.error(&error),
+                }
+            },
+            Some(Ok(Command::Unban(address))) => match session.unban(&address) {
+                Ok(()) => session.notice(&format!("unbanned {}", address)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Mute(nick, duration))) => match session.mute(&nick, duration) {
+                Ok(()) => session.notice(&format!("muted {}", nick)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Unmute(nick))) => match session.unmute(&nick) {
+                Ok(()) => session.notice(&format!("unmuted {}", nick)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Op(nick))) => match session.op(&nick) {
+                Ok(()) => session.notice(&format!("{} is now an operator", nick)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Send(nick, file, size))) => match session.send_file(&nick, &file, size) {
+                Ok(ref transfer) if transfer.is_complete() => {},
+                Ok(transfer) => {
+                    session.notice(&format!("upload {} ready: send {} with /chunk {} <offset> <base64>",
+                                            transfer.id, transfer.name, transfer.id))
+                },
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Chunk(id, offset, data))) => {
+                if let Err(error) = session.upload(id, offset, &data) {
+                    session.error(&error);
+                }
+            },
+            Some(Ok(Command::Accept(id))) => {
+                if let Err(error) = session.accept(id) {
+                    session.error(&error);
+                }
+            },
+            Some(Ok(Command::Decline(id))) => match session.decline(id) {
+                Ok(()) => session.notice(&format!("declined file {}", id)),
+                Err(error) => session.error(&error),
+            },
+            Some(Ok(Command::Download(id))) => {
+                if let Err(error) = session.download(id) {
+                    session.error(&error);
+                }
+            },
+            Some(Ok(Command::Stats)) => match session.stats() {
+                Ok(samples) => {
+                    for sample in samples {
+                        session.notice(&format!("{}: {}", sample.help, sample.value));
+                    }
+                },
+                Err(error) => session.error(&error),
+            },
+            Some(Err(error)) => session.error(&error),
+            None if line.is_empty() => {},
+            None => {
+                if let Err(error) = session.say(&room, line) {
+                    session.error(&error);
+                }
+            },
+        }
+    }
+
+    log!(Info, "client disconnected", id = session.id, name = session.name);
+    session.close();
+}
+
+// Events from clients are delivered in order, then shown to the plugins; what
+// the plugins make of them is delivered before the next event from a client.
+pub fn spawn_broadcast_thread (state: Shared, rx: Receiver<Event>, mut plugins: Vec<Box<dyn Plugin>>) {
+    thread::spawn(move || {
+        let mut last_tick = Instant::now();
+        loop {
+            let mut pending = VecDeque::new();
+            match rx.recv_timeout(PLUGIN_TICK) {
+                Ok(event) => {
+                    metrics::uncount(&METRICS.queued, 1);
+                    pending.push_back(event);
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let mut state = state.lock().unwrap();
+            let mut actions = Vec::new();
+            if last_tick.elapsed() >= PLUGIN_TICK {
+                last_tick = Instant::now();
+                for plugin in plugins.iter_mut() {
+                    let name = plugin.name().to_string();
+                    actions.extend(plugin.on_tick().into_iter().map(|a| (name.clone(), a)));
+                }
+            }
+            loop {
+                for (name, action) in actions.drain(..) {
+                    pending.extend(plugin::perform(&mut state, &name, action));
+                }
+                let event = match pending.pop_front() {
+                    Some(event) => event,
+                    None => break,
+                };
+                deliver(&mut state, &event);
+                // what happens on linked servers is up to their plugins
+                if event.sender() != Some(PLUGIN_ID) && event.sender() != Some(LINK_ID) {
+                    for plugin in plugins.iter_mut() {
+                        let name = plugin.name().to_string();
+                        actions.extend(plugin.on_event(&event).into_iter().map(|a| (name.clone(), a)));
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn deliver (state: &mut State, event: &Event) {
+    match *event {
+        Event::Message { ref room, ref name, ref text, .. } => {
+            metrics::count(&METRICS.messages, 1);
+            state.history.push(Entry::new(room, name, text));
+            state.record_mentions(room, name, text);
+        },
+        Event::Private { .. } => metrics::count(&METRICS.private_messages, 1),
+        _ => {},
+    }
+    if let Some(ref mut transcripts) = state.transcripts {
+        transcripts.record(event);
+    }
+    state.broadcast(event);
+    link::relay(state, event);
+    if let Event::Shutdown = *event {
+        for client in state.clients.values_mut() {
+            client.shutdown();
+        }
+        state.links.close();
+    }
+}
+
+pub fn spawn_keepalive_thread (state: Shared) {
+    thread::spawn(move || {
+        for token in 1.. {
+            thread::sleep(KEEPALIVE_CHECK);
+            let mut state = state.lock().unwrap();
+            state.keep_alive(token);
+            state.transfers.expire();
+        }
+    });
+}
+
+// The shutdown notice goes through the broadcast channel like any other event,
+// so every message sent before it is still delivered. Closing the streams ends
+// the client threads, which are given until `timeout` to clean up.
+pub fn shutdown (state: &Shared, tx: &Sender<Event>, timeout: Duration) {
+    state.lock().unwrap().closing = true;
+    metrics::count(&METRICS.queued, 1);
+    let _ = tx.send(Event::Shutdown);
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline && !state.lock().unwrap().clients.is_empty() {
+        thread::sleep(Duration::from_millis(20));
+    }
+    if let Err(e) = state.lock().unwrap().history.sync() {
+        log!(Error, "cannot write history file", error = e);
+    }
+}
@@ -0,0 +1,107 @@
+pub extern crate rustls;
+
+use std::io;
+use std::io::prelude::*;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use self::rustls::{ServerConfig, ServerConnection, StreamOwned};
+use self::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use self::rustls::pki_types::pem::PemObject;
+
+
+// how long a reader may hold the connection before giving writers a turn
+const READ_SLICE: Duration = Duration::from_millis(50);
+
+pub fn load_config (cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("cannot read certificate {}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("cannot read private key {}: {}", key_path.display(), e))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid certificate or key: {}", e))?;
+    Ok(Arc::new(config))
+}
+
+
+// A TLS session is a single state machine for both directions, so the reading
+// and the writing half share it. Reads wait on the socket for a short slice at
+// a time, releasing the lock in between so broadcasts can still get through.
+pub struct TlsStream {
+    inner: Arc<Mutex<StreamOwned<ServerConnection, TcpStream>>>,
+}
+
+impl TlsStream {
+    pub fn new (config: Arc<ServerConfig>, socket: TcpStream) -> io::Result<TlsStream> {
+        let connection = ServerConnection::new(config)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        socket.set_read_timeout(Some(READ_SLICE))?;
+        Ok(TlsStream { inner: Arc::new(Mutex::new(StreamOwned::new(connection, socket))) })
+    }
+
+    pub fn try_clone (&self) -> io::Result<TlsStream> {
+        Ok(TlsStream { inner: self.inner.clone() })
+    }
+
+    pub fn peer_addr (&self) -> io::Result<SocketAddr> {
+        self.inner.lock().unwrap().sock.peer_addr()
+    }
+
+    pub fn set_write_timeout (&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.lock().unwrap().sock.set_write_timeout(timeout)
+    }
+
+    pub fn shutdown (&self) -> io::Result<()> {
+        let mut guard = self.inner.lock().unwrap();
+        guard.conn.send_close_notify();
+        let _ = send_records(&mut guard);
+        guard.sock.shutdown(Shutdown::Both)
+    }
+}
+
+fn is_timeout (error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut
+}
+
+impl Read for TlsStream {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.lock().unwrap().read(buf) {
+                Err(ref e) if is_timeout(e) => {},
+                result => return result,
+            }
+        }
+    }
+}
+
+// Writes never drive the handshake themselves, that is left to the reader:
+// until it completes rustls keeps the plaintext and sends it afterwards.
+impl Write for TlsStream {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut guard = self.inner.lock().unwrap();
+        let written = guard.conn.writer().write(buf)?;
+        send_records(&mut guard)?;
+        Ok(written)
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        send_records(&mut self.inner.lock().unwrap())
+    }
+}
+
+fn send_records (stream: &mut StreamOwned<ServerConnection, TcpStream>) -> io::Result<()> {
+    if !stream.conn.is_handshaking() {
+        while stream.conn.wants_write() {
+            stream.conn.write_tls(&mut stream.sock)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+extern crate time;
+
+use std::io;
+use std::io::prelude::*;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+use std::collections::HashMap;
+
+use event::Event;
+use log;
+
+
+// What was said in each room, as people saw it, in `<dir>/<room>/<date>.log`.
+// A new file is started on the first event of every day, local time.
+pub struct Transcripts {
+    dir: PathBuf,
+    // the open file of every room and the date it is for
+    files: HashMap<String, (String, File)>,
+}
+
+impl Transcripts {
+    pub fn new (dir: PathBuf) -> Transcripts {
+        Transcripts { dir, files: HashMap::new() }
+    }
+
+    pub fn record (&mut self, event: &Event) {
+        let rooms = match *event {
+            Event::Connected { ref room, .. } | Event::Joined { ref room, .. } |
+            Event::Left { ref room, .. } | Event::Message { ref room, .. } => vec![room.clone()],
+            Event::Disconnected { ref rooms, .. } | Event::Nick { ref rooms, .. } => rooms.clone(),
+            Event::Private { .. } | Event::Shutdown => return,
+        };
+        let now = time::now();
+        let line = format!("[{}] {}", now.strftime("%H:%M:%S").unwrap(), redact(event).to_text());
+        let date = now.strftime("%Y-%m-%d").unwrap().to_string();
+        for room in rooms {
+            if let Err(e) = self.write(&room, &date, &line) {
+                log!(Error, "cannot write transcript", room = room, error = e);
+                self.files.remove(&room);
+            }
+        }
+    }
+
+    fn write (&mut self, room: &str, date: &str, line: &str) -> io::Result<()> {
+        let current = self.files.get(room).map(|file| file.0 == date).unwrap_or(false);
+        if !current {
+            let dir = self.dir.join(file_name(room));
+            fs::create_dir_all(&dir)?;
+            let file = OpenOptions::new().create(true).append(true).open(dir.join(format!("{}.log", date)))?;
+            self.files.insert(room.to_string(), (date.to_string(), file));
+        }
+        self.files.get_mut(room).unwrap().1.write_all(line.as_bytes())
+    }
+}
+
+// room names may contain anything but spaces and commas, so everything
+// which could lead out of the transcript directory is escaped
+fn file_name (room: &str) -> String {
+    room.bytes().map(|b| {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            (b as char).to_string()
+        } else {
+            format!("%{:02X}", b)
+        }
+    }).collect()
+}
+
+// with addresses hidden, clients still named after theirs show up as a dash
+fn redact (event: &Event) -> Event {
+    let hide = |name: &String| {
+        if log::hides_addresses() && log::is_address(name) { "-".to_string() } else { name.clone() }
+    };
+    match event.clone() {
+        Event::Connected { id, room, name } => Event::Connected { id, room, name: hide(&name) },
+        Event::Disconnected { id, rooms, name, reason } => {
+            Event::Disconnected { id, rooms, name: hide(&name), reason }
+        },
+        Event::Joined { id, room, name } => Event::Joined { id, room, name: hide(&name) },
+        Event::Left { id, room, name } => Event::Left { id, room, name: hide(&name) },
+        Event::Message { id, room, name, text } => Event::Message { id, room, name: hide(&name), text },
+        Event::Nick { id, rooms, old, new } => Event::Nick { id, rooms, old: hide(&old), new },
+        event => event,
+    }
+}
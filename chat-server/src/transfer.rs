@@ -0,0 +1,235 @@
+extern crate base64;
+
+use std::io;
+use std::io::prelude::*;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use std::collections::HashMap;
+
+use self::base64::Engine;
+
+
+// the most file data a client sends or receives in one line, before base64
+pub const CHUNK_SIZE: usize = 32 * 1024;
+
+// how long a file waits in the spool for its recipient
+const TRANSFER_TTL: Duration = Duration::from_secs(60 * 60);
+
+// unfinished and undownloaded files one client may have in the spool
+const MAX_PENDING: usize = 5;
+
+pub fn encode (data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+fn decode (data: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+// the part of a path after its last separator, with spaces replaced so
+// the name is a single word in the text protocol
+fn file_name (path: &str) -> Option<String> {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or("");
+    let name: String = name.chars().map(|c| if c.is_whitespace() || c.is_control() { '_' } else { c }).collect();
+    match name.as_str() {
+        "" | "." | ".." => None,
+        _ => Some(name.chars().take(100).collect()),
+    }
+}
+
+
+#[derive(Clone)]
+pub struct Transfer {
+    pub id: u64,
+    pub from: String,
+    pub to: String,
+    pub name: String,
+    pub size: u64,
+    received: u64,
+    pub accepted: bool,
+    created: Instant,
+}
+
+impl Transfer {
+    pub fn is_complete (&self) -> bool {
+        self.received == self.size
+    }
+}
+
+// one line of a download, as sent to the recipient
+pub enum Download<'a> {
+    Start { id: u64, size: u64, name: &'a str },
+    Chunk { id: u64, offset: u64, data: String },
+    End { id: u64 },
+}
+
+impl<'a> Download<'a> {
+    pub fn to_text (&self) -> String {
+        match *self {
+            Download::Start { id, size, name } => format!("file {} {} {}\n", id, size, name),
+            Download::Chunk { id, offset, ref data } => format!("chunk {} {} {}\n", id, offset, data),
+            Download::End { id } => format!("end {}\n", id),
+        }
+    }
+}
+
+
+// Files on their way from one client to another. The data is kept in the
+// spool directory, one file per transfer named after its id, from the first
+// chunk until the recipient declines it or it expires.
+pub struct Transfers {
+    spool: Option<PathBuf>,
+    max_size: u64,
+    next_id: u64,
+    transfers: HashMap<u64, Transfer>,
+}
+
+impl Transfers {
+    pub fn disabled () -> Transfers {
+        Transfers { spool: None, max_size: 0, next_id: 1, transfers: HashMap::new() }
+    }
+
+    // whatever a previous run left in the spool can no longer be claimed;
+    // only files named after a transfer id are ours to remove
+    pub fn open (spool: &Path, max_size: u64) -> io::Result<Transfers> {
+        fs::create_dir_all(spool)?;
+        for entry in fs::read_dir(spool)? {
+            let path = entry?.path();
+            let is_transfer = path.file_name().and_then(|name| name.to_str())
+                .map(|name| name.parse::<u64>().is_ok())
+                .unwrap_or(false);
+            if is_transfer && path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Transfers { spool: Some(spool.to_path_buf()), max_size, next_id: 1, transfers: HashMap::new() })
+    }
+
+    fn path (&self, id: u64) -> Result<PathBuf, String> {
+        match self.spool {
+            Some(ref spool) => Ok(spool.join(id.to_string())),
+            None => Err("file transfers are disabled on this server".to_string()),
+        }
+    }
+
+    pub fn offer (&mut self, from: &str, to: &str, path: &str, size: u64) -> Result<Transfer, String> {
+        self.path(0)?;
+        let name = file_name(path).ok_or(format!("invalid file name {}", path))?;
+        if size > self.max_size {
+            return Err(format!("{} is too large, files may have at most {} bytes", name, self.max_size));
+        }
+        if self.transfers.values().filter(|t| t.from == from && !t.accepted).count() >= MAX_PENDING {
+            return Err(format!("you already have {} files waiting to be accepted", MAX_PENDING));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        File::create(self.path(id)?).map_err(|e| format!("cannot store {}: {}", name, e))?;
+        let transfer = Transfer {
+            id,
+            from: from.to_string(),
+            to: to.to_string(),
+            name,
+            size,
+            received: 0,
+            accepted: false,
+            created: Instant::now(),
+        };
+        self.transfers.insert(id, transfer.clone());
+        Ok(transfer)
+    }
+
+    // chunks are numbered by their offset, so a chunk lost to flood control is
+    // noticed; returns the transfer once it is complete
+    pub fn append (&mut self, from: &str, id: u64, offset: u64, data: &str) -> Result<Option<Transfer>, String> {
+        let path = self.path(id)?;
+        let transfer = match self.transfers.get_mut(&id) {
+            Some(transfer) if transfer.from == from && !transfer.is_complete() => transfer,
+            _ => return Err(format!("no upload {}", id)),
+        };
+        if offset != transfer.received {
+            return Err(format!("upload {} continues at offset {}", id, transfer.received));
+        }
+        let data = decode(data).ok_or(format!("invalid base64 in upload {}", id))?;
+        if data.len() > CHUNK_SIZE || transfer.received + data.len() as u64 > transfer.size {
+            return Err(format!("too much data for upload {}", id));
+        }
+        OpenOptions::new().append(true).open(path)
+            .and_then(|mut file| file.write_all(&data))
+            .map_err(|e| format!("cannot store {}: {}", transfer.name, e))?;
+        transfer.received += data.len() as u64;
+        Ok(if transfer.is_complete() { Some(transfer.clone()) } else { None })
+    }
+
+    // the recipient's view of a file, only once it has been uploaded
+    fn offered (&mut self, to: &str, id: u64) -> Result<&mut Transfer, String> {
+        match self.transfers.get_mut(&id) {
+            Some(transfer) if transfer.to == to && transfer.is_complete() => Ok(transfer),
+            _ => Err(format!("no file {}", id)),
+        }
+    }
+
+    pub fn accept (&mut self, to: &str, id: u64) -> Result<Transfer, String> {
+        let transfer = self.offered(to, id)?;
+        transfer.accepted = true;
+        Ok(transfer.clone())
+    }
+
+    pub fn decline (&mut self, to: &str, id: u64) -> Result<Transfer, String> {
+        self.offered(to, id)?;
+        Ok(self.remove(id).unwrap())
+    }
+
+    // the file is read in chunks outside the state lock
+    pub fn download (&mut self, to: &str, id: u64) -> Result<(Transfer, File), String> {
+        let path = self.path(id)?;
+        let transfer = self.offered(to, id)?;
+        if !transfer.accepted {
+            return Err(format!("use /accept {} first", id));
+        }
+        let file = File::open(path).map_err(|e| format!("cannot read {}: {}", transfer.name, e))?;
+        Ok((transfer.clone(), file))
+    }
+
+    // files follow their sender and recipient through nickname changes
+    pub fn rename (&mut self, old: &str, new: &str) {
+        for transfer in self.transfers.values_mut() {
+            if transfer.from == old {
+                transfer.from = new.to_string();
+            }
+            if transfer.to == old {
+                transfer.to = new.to_string();
+            }
+        }
+    }
+
+    // drops the unfinished uploads of a client which has left
+    pub fn abandon (&mut self, from: &str) {
+        let ids: Vec<u64> = self.transfers.values()
+            .filter(|t| t.from == from && !t.is_complete())
+            .map(|t| t.id)
+            .collect();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    pub fn expire (&mut self) {
+        let ids: Vec<u64> = self.transfers.values()
+            .filter(|t| t.created.elapsed() >= TRANSFER_TTL)
+            .map(|t| t.id)
+            .collect();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    fn remove (&mut self, id: u64) -> Option<Transfer> {
+        if let Ok(path) = self.path(id) {
+            let _ = fs::remove_file(path);
+        }
+        self.transfers.remove(&id)
+    }
+}
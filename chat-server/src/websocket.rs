@@ -0,0 +1,209 @@
+// WebSocket transport (RFC 6455) for browser clients. Every text message is
+// handled like a line of the plain-text protocol, and everything the server
+// sends goes out as one text frame per line.
+
+extern crate base64;
+extern crate sha1;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use std::sync::mpsc::Sender;
+
+use self::base64::Engine;
+use self::sha1::{Sha1, Digest};
+
+use event::Event;
+use net;
+use net::Stream;
+use server::{Protocol, Session, Shared, run_text_session};
+
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+
+fn accept_key (key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(sha1.finalize())
+}
+
+// reads the HTTP upgrade request and answers it, returning false when the
+// request was not a websocket handshake
+fn handshake (reader: &mut BufReader<Stream>, stream: &mut Stream) -> io::Result<bool> {
+    let mut key = None;
+    let mut upgrade = false;
+    let (request_line, headers) = net::read_request(&mut *reader)?;
+    for line in headers {
+        if let Some(i) = line.find(':') {
+            let (name, value) = (line[..i].trim().to_lowercase(), line[i + 1..].trim());
+            match name.as_str() {
+                "upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+                "sec-websocket-key" => key = Some(value.to_string()),
+                _ => {},
+            }
+        }
+    }
+
+    match key {
+        Some(ref key) if upgrade && request_line.starts_with("GET ") => {
+            write!(stream, "HTTP/1.1 101 Switching Protocols\r\n\
+                            Upgrade: websocket\r\n\
+                            Connection: Upgrade\r\n\
+                            Sec-WebSocket-Accept: {}\r\n\r\n", accept_key(key))?;
+            Ok(true)
+        },
+        _ => {
+            write!(stream, "HTTP/1.1 400 Bad Request\r\n\
+                            Content-Length: 0\r\n\
+                            Connection: close\r\n\r\n")?;
+            Ok(false)
+        },
+    }
+}
+
+fn encode_frame (opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let length = payload.len();
+    if length < 126 {
+        frame.push(length as u8);
+    } else if length <= 0xFFFF {
+        frame.push(126);
+        frame.extend_from_slice(&(length as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(length as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn text_frames (text: &str) -> Vec<u8> {
+    text.lines().flat_map(|line| encode_frame(OPCODE_TEXT, line.as_bytes())).collect()
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+pub fn ping_frame (token: &str) -> Vec<u8> {
+    encode_frame(OPCODE_PING, token.as_bytes())
+}
+
+fn read_frame<R: Read> (reader: &mut R) -> io::Result<Frame> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut length = [0; 2];
+            reader.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as u64
+        },
+        127 => {
+            let mut length = [0; 8];
+            reader.read_exact(&mut length)?;
+            u64::from_be_bytes(length)
+        },
+        length => length as u64,
+    };
+    if !masked {
+        return Err(invalid("client frames must be masked"));
+    }
+    if length > MAX_MESSAGE_SIZE as u64 {
+        return Err(invalid("frame too large"));
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame { fin, opcode, payload })
+}
+
+// yields the lines of every text message, answering pings and close frames
+// on the way; writes go through the state so they never interleave with a broadcast
+struct Messages {
+    reader: BufReader<Stream>,
+    state: Shared,
+    id: usize,
+    pending: Vec<String>,
+}
+
+impl Messages {
+    fn send (&self, frame: &[u8]) {
+        self.state.lock().unwrap().send_to(self.id, frame);
+    }
+
+    fn next_message (&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        loop {
+            let frame = read_frame(&mut self.reader)?;
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    message.extend_from_slice(&frame.payload);
+                    if message.len() > MAX_MESSAGE_SIZE {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+                    }
+                    if frame.fin {
+                        return String::from_utf8(message)
+                            .map(Some)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+                    }
+                },
+                OPCODE_PING => self.send(&encode_frame(OPCODE_PONG, &frame.payload)),
+                OPCODE_PONG => self.state.lock().unwrap().touch(self.id),
+                OPCODE_CLOSE => {
+                    self.send(&encode_frame(OPCODE_CLOSE, &frame.payload[..frame.payload.len().min(2)]));
+                    return Ok(None);
+                },
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown opcode")),
+            }
+        }
+    }
+}
+
+impl Iterator for Messages {
+    type Item = String;
+
+    fn next (&mut self) -> Option<String> {
+        while self.pending.is_empty() {
+            match self.next_message() {
+                Ok(Some(message)) => self.pending = message.lines().rev().map(String::from).collect(),
+                _ => return None,
+            }
+        }
+        self.pending.pop()
+    }
+}
+
+
+pub fn serve (id: usize, mut stream: Stream, state: Shared, tx: Sender<Event>) {
+    let mut reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(_) => return,
+    };
+    match handshake(&mut reader, &mut stream) {
+        Ok(true) => {},
+        _ => return,
+    }
+    let messages = Messages { reader, state: state.clone(), id, pending: Vec::new() };
+    run_text_session(Session::new(id, "", state, tx), stream, Protocol::WebSocket, messages);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Server, Peer, create_account, join, temp_dir};
+
+
+fn start (accounts: &Path, listeners: &[&str], extra: &[&str]) -> Server {
+    let mut args = vec!["--accounts-file", accounts.to_str().unwrap()];
+    args.extend_from_slice(extra);
+    Server::start_with(listeners, &args)
+}
+
+
+#[test]
+fn registered_nicknames_need_their_password () {
+    let dir = temp_dir("accounts-password");
+    let server = start(&dir.join("accounts"), &[], &[]);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    alice.send("/register secret");
+    alice.expect("registered and logged in as alice");
+    alice.send("/register again");
+    alice.expect("error: nickname alice is already registered");
+    alice.send("/nick alicia");
+    bob.expect("alice is now known as alicia");
+
+    bob.send("/nick alice");
+    bob.expect("error: nickname alice is registered, use /login");
+    bob.send("/login alice wrong");
+    bob.expect("error: wrong password");
+    bob.send("/login nobody secret");
+    bob.expect("error: nickname nobody is not registered");
+    bob.send("/login alice secret");
+    bob.expect("logged in as alice");
+    alice.expect("bob is now known as alice");
+}
+
+#[test]
+fn a_login_which_cannot_take_the_nickname_leaves_the_client_logged_out () {
+    let dir = temp_dir("accounts-taken");
+    let accounts = dir.join("accounts");
+    // the account is older than the plugin which now speaks under its name
+    create_account(&accounts, "dice", "secret");
+    let server = start(&accounts, &[], &["--plugin", "dice", "--operator", "dice"]);
+    let mut bob = join(&server, "bob");
+    let _carol = join(&server, "carol");
+
+    bob.send("/login dice secret");
+    bob.expect("error: nickname dice is already in use");
+    bob.send("/inbox");
+    bob.expect("error: you need to /register or /login to have an inbox");
+    bob.send("/kick carol");
+    bob.expect("error: you are not an operator");
+}
+
+#[test]
+fn accounts_survive_a_restart () {
+    let dir = temp_dir("accounts-restart");
+    let accounts = dir.join("accounts");
+    let server = start(&accounts, &[], &[]);
+    let mut alice = join(&server, "alice");
+    alice.send("/register secret");
+    alice.expect("registered and logged in as alice");
+    drop(server);
+
+    let stored = fs::read_to_string(&accounts).unwrap();
+    assert!(stored.starts_with("alice\t"));
+    assert!(!stored.contains("secret"));
+
+    let server = start(&accounts, &[], &[]);
+    let mut again = Peer::connect(server.port("--listen"));
+    again.send("/login alice wrong");
+    again.expect("error: wrong password");
+    again.send("/login alice secret");
+    again.expect("logged in as alice");
+    again.send("/register secret");
+    again.expect("error: nickname alice is already registered");
+}
+
+#[test]
+fn only_logged_in_clients_may_talk_with_require_auth () {
+    let dir = temp_dir("accounts-require-auth");
+    let server = start(&dir.join("accounts"), &[], &["--require-auth", "true"]);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    bob.send("hello?");
+    bob.expect("error: you need to /register or /login before talking");
+    alice.send("/register secret");
+    alice.expect("registered and logged in as alice");
+    alice.send("hello bob");
+    assert_eq!(bob.expect("hello bob"), "alice: hello bob");
+}
+
+#[test]
+fn irc_clients_log_in_with_pass () {
+    let dir = temp_dir("accounts-irc");
+    let server = start(&dir.join("accounts"), &["--irc-listen"], &[]);
+    let mut text = join(&server, "alice");
+    text.send("/register secret");
+    text.expect("registered and logged in as alice");
+    text.send("/nick somebody");
+    text.expect("is now known as somebody");
+
+    let mut irc = Peer::connect(server.port("--irc-listen"));
+    irc.send("NICK alice");
+    irc.send("USER alice 0 * :alice");
+    irc.expect(" 464 * :Password incorrect");
+    irc.send("PASS wrong");
+    irc.send("NICK alice");
+    irc.expect(" 464 * :Password incorrect");
+    irc.send("PASS secret");
+    irc.send("NICK alice");
+    irc.expect(" 001 alice ");
+}
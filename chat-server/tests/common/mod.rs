@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+
+extern crate chat_server;
+
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+// a chat-server process listening on ephemeral loopback ports, killed when dropped
+pub struct Server {
+    process: Child,
+    ports: Vec<(String, u16)>,
+    // what the server has logged so far
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+    // a plain-text listener is always started, `listeners` adds more of the
+    // given kinds, e.g. `--irc-listen`; `extra` are passed through unchanged.
+    // The server binds port 0 itself and the ports are read from its log, so
+    // no other test can take them in between.
+    pub fn start_with (listeners: &[&str], extra: &[&str]) -> Server {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_chat-server"));
+        let options: Vec<&str> = ["--listen"].iter().chain(listeners.iter()).cloned().collect();
+        for option in options.iter() {
+            command.arg(option).arg("127.0.0.1:0");
+        }
+        let mut process = command.args(extra).stdout(Stdio::piped()).spawn().unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        let lines = log.clone();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                lines.lock().unwrap().push(line.unwrap());
+            }
+        });
+        let mut server = Server { process, ports: Vec::new(), log };
+        server.ports = server.wait_for_ports(&options);
+        server
+    }
+
+    // the port of every option, in the order the server logs its listeners
+    fn wait_for_ports (&mut self, options: &[&str]) -> Vec<(String, u16)> {
+        let start = Instant::now();
+        loop {
+            let log = self.log();
+            let mut used = Vec::new();
+            let ports: Option<Vec<(String, u16)>> = options.iter().map(|option| {
+                let (pattern, key) = listening(option);
+                let (index, line) = log.iter().enumerate()
+                    .find(|(index, line)| !used.contains(index) && line.contains(pattern) && line.contains(key))?;
+                used.push(index);
+                let port = line.split(key).nth(1)?.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()?;
+                Some((option.to_string(), port))
+            }).collect();
+            if let Some(ports) = ports {
+                return ports;
+            }
+            if self.process.try_wait().unwrap().is_some() || start.elapsed() > Duration::from_secs(10) {
+                panic!("server did not start: {:?}", log);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    pub fn start (listeners: &[&str]) -> Server {
+        Server::start_with(listeners, &[])
+    }
+
+    pub fn port (&self, option: &str) -> u16 {
+        self.ports.iter().find(|p| p.0 == option).unwrap().1
+    }
+
+    // waits a while for a log line containing `pattern` and returns it
+    pub fn expect_log (&self, pattern: &str) -> String {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(line) = self.log().into_iter().find(|line| line.contains(pattern)) {
+                return line;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the server did not log {:?}", pattern);
+    }
+
+    pub fn log (&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+
+    // asks the server to shut down with SIGTERM and waits for it to exit
+    pub fn terminate (&mut self) -> ExitStatus {
+        let pid = self.process.id().to_string();
+        Command::new("kill").args(["-TERM", &pid]).status().unwrap();
+        self.process.wait().unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop (&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// a server inside the test process on an ephemeral loopback port, for tests
+// which look at its state; it runs until the test binary exits
+pub fn start_in_process (options: &[&str]) -> (chat_server::Server, u16) {
+    let args = ["--listen", "127.0.0.1:0"].iter().chain(options.iter()).map(|arg| arg.to_string());
+    let server = chat_server::start(chat_server::Config::from_args(args).unwrap()).unwrap();
+    let port = server.address(chat_server::Protocol::Text).unwrap().port();
+    (server, port)
+}
+
+// a plain-text client of `server` which has taken `nick`
+pub fn join (server: &Server, nick: &str) -> Peer<TcpStream> {
+    Peer::join(server.port("--listen"), nick)
+}
+
+// registers `nick` in the accounts file with a server of its own, as configured
+// operators need to be before the server naming them starts
+pub fn create_account (accounts: &Path, nick: &str, password: &str) {
+    let server = Server::start_with(&[], &["--accounts-file", accounts.to_str().unwrap()]);
+    let mut peer = join(&server, nick);
+    peer.send(&format!("/register {}", password));
+    peer.expect(&format!("registered and logged in as {}", nick));
+}
+
+// an empty directory named after the test, removed with everything in it when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+pub fn temp_dir (name: &str) -> TempDir {
+    let path = env::temp_dir().join(format!("chat-server-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    TempDir { path }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref (&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop (&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// what the server logs once the listener for `option` is bound, and the key
+// its address follows; TLS is logged as text, after the plain listener
+fn listening (option: &str) -> (&'static str, &'static str) {
+    match option {
+        "--listen" | "--tls-listen" => (" protocol=text ", "listen=127.0.0.1:"),
+        "--irc-listen" => (" protocol=irc ", "listen=127.0.0.1:"),
+        "--ws-listen" => (" protocol=websocket ", "listen=127.0.0.1:"),
+        "--link-listen" => ("msg=\"accepting links\"", "listen=127.0.0.1:"),
+        "--metrics-listen" => ("msg=\"serving metrics\"", "url=http://127.0.0.1:"),
+        _ => panic!("no listener option {}", option),
+    }
+}
+
+// only for a port which has to be known before its server starts
+pub fn free_port () -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// the server takes a moment to start listening
+pub fn connect (port: u16) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("server did not start on port {}", port);
+}
+
+
+pub struct Peer<S: Read + Write> {
+    reader: BufReader<S>,
+}
+
+impl Peer<TcpStream> {
+    pub fn connect (port: u16) -> Peer<TcpStream> {
+        Peer::new(connect(port))
+    }
+
+    pub fn join (port: u16, nick: &str) -> Peer<TcpStream> {
+        let mut peer = Peer::connect(port);
+        peer.send(&format!("/nick {}", nick));
+        peer.expect(&format!("is now known as {}", nick));
+        peer
+    }
+
+    pub fn register (port: u16, nick: &str) -> Peer<TcpStream> {
+        let mut peer = Peer::connect(port);
+        peer.send(&format!("NICK {}", nick));
+        peer.send(&format!("USER {} 0 * :{}", nick, nick));
+        peer.expect(" 001 ");
+        peer.expect(" 422 ");
+        peer
+    }
+}
+
+impl<S: Read + Write> Peer<S> {
+    pub fn new (stream: S) -> Peer<S> {
+        Peer { reader: BufReader::new(stream) }
+    }
+
+    pub fn send (&mut self, line: &str) {
+        let stream = self.reader.get_mut();
+        stream.write_all(format!("{}\r\n", line).as_bytes()).unwrap();
+        stream.flush().unwrap();
+    }
+
+    // reads lines until one contains `pattern`, returning that line
+    pub fn expect (&mut self, pattern: &str) -> String {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed while waiting for {:?}", pattern),
+                Ok(_) if line.contains(pattern) => return line.trim_end().to_string(),
+                Ok(_) => {},
+                Err(e) => panic!("{} while waiting for {:?}", e, pattern),
+            }
+        }
+    }
+
+    // the very next line, which must be exactly `line`
+    pub fn expect_next (&mut self, line: &str) {
+        let mut next = String::new();
+        match self.reader.read_line(&mut next) {
+            Ok(0) => panic!("connection closed while waiting for {:?}", line),
+            Ok(_) => assert_eq!(next.trim_end(), line),
+            Err(e) => panic!("{} while waiting for {:?}", e, line),
+        }
+    }
+
+    // A scripted exchange, one step per line: `> text` is sent, any other
+    // line is expected as the very next line from the server.
+    pub fn run (&mut self, script: &str) {
+        for step in script.lines().map(str::trim).filter(|step| !step.is_empty()) {
+            match step.strip_prefix("> ") {
+                Some(line) => self.send(line),
+                None => self.expect_next(step),
+            }
+        }
+    }
+
+    // reads whatever is left until the server hangs up
+    pub fn expect_closed (&mut self) {
+        let mut rest = Vec::new();
+        if let Err(e) = self.reader.read_to_end(&mut rest) {
+            panic!("{} while waiting for the connection to close", e);
+        }
+    }
+}
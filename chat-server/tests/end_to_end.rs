@@ -0,0 +1,87 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{start_in_process, Peer};
+
+
+#[test]
+fn messages_arrive_in_the_order_they_were_sent () {
+    let (_server, port) = start_in_process(&["--flood-messages", "0"]);
+    let mut alice = Peer::join(port, "alice");
+    let mut bob = Peer::join(port, "bob");
+    alice.expect("is now known as bob");
+
+    for i in 0..50 {
+        alice.send(&format!("message {}", i));
+    }
+    for i in 0..50 {
+        bob.expect_next(&format!("alice: message {}", i));
+    }
+    bob.run("
+        > done
+        bob: done
+    ");
+    for i in 0..50 {
+        alice.expect_next(&format!("alice: message {}", i));
+    }
+    alice.expect_next("bob: done");
+}
+
+#[test]
+fn joins_and_leaves_are_announced_to_the_room () {
+    let (_server, port) = start_in_process(&[]);
+    let mut alice = Peer::join(port, "alice");
+    let mut bob = Peer::join(port, "bob");
+    alice.expect("is now known as bob");
+
+    bob.run("
+        > /join rust
+        bob left lobby
+        bob joined rust
+        > hello rust
+        bob: hello rust
+    ");
+    // the history comes straight back while the notice that alice left goes
+    // through the broadcast thread, so only their own order is certain
+    alice.run("
+        bob left lobby
+        > /join rust
+    ");
+    alice.expect("-- last 1 messages in rust --");
+    alice.expect("bob: hello rust");
+    alice.expect_next("--");
+    bob.expect_next("alice joined rust");
+}
+
+#[test]
+fn disconnected_clients_are_cleaned_up () {
+    let (server, port) = start_in_process(&[]);
+    let mut alice = Peer::join(port, "alice");
+    let bob = Peer::join(port, "bob");
+    alice.expect("is now known as bob");
+    assert_eq!(server.client_count(), 2);
+    assert_eq!(server.names("lobby"), vec!["alice", "bob"]);
+
+    drop(bob);
+    alice.expect_next("bob disconnected");
+    assert_eq!(server.client_count(), 1);
+    assert_eq!(server.names("lobby"), vec!["alice"]);
+
+    // the nickname is free again
+    let mut bob = Peer::join(port, "bob");
+    alice.expect("is now known as bob");
+    bob.run("
+        > /names
+        names in lobby: alice bob
+    ");
+
+    // as is everything once the server shuts down
+    server.shutdown(Duration::from_secs(5));
+    alice.expect("the server is shutting down");
+    alice.expect_closed();
+    while server.client_count() > 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::net::TcpStream;
+
+use common::{Server, Peer, join, temp_dir};
+
+
+// reads up to the next backlog and returns its header and messages, without the times
+fn backlog (peer: &mut Peer<TcpStream>) -> (String, Vec<String>) {
+    let header = peer.expect("-- last ");
+    let mut messages = Vec::new();
+    loop {
+        let line = peer.expect("");
+        if line == "--" {
+            return (header, messages);
+        }
+        messages.push(line.split_once("] ").unwrap().1.to_string());
+    }
+}
+
+fn say (peer: &mut Peer<TcpStream>, nick: &str, messages: &[&str]) {
+    for message in messages {
+        peer.send(message);
+        peer.expect(&format!("{}: {}", nick, message));
+    }
+}
+
+
+#[test]
+fn recent_messages_are_replayed_on_joining_and_on_request () {
+    let server = Server::start_with(&[], &["--history-size", "3"]);
+    let mut alice = join(&server, "alice");
+    say(&mut alice, "alice", &["one", "two", "three", "four"]);
+
+    let mut bob = Peer::connect(server.port("--listen"));
+    assert_eq!(backlog(&mut bob), ("-- last 3 messages in lobby --".to_string(),
+                                   vec!["alice: two".to_string(), "alice: three".to_string(),
+                                        "alice: four".to_string()]));
+    bob.send("/history 2");
+    assert_eq!(backlog(&mut bob), ("-- last 2 messages in lobby --".to_string(),
+                                   vec!["alice: three".to_string(), "alice: four".to_string()]));
+    // asking for more than is kept gives what there is
+    bob.send("/history 100");
+    assert_eq!(backlog(&mut bob).1, vec!["alice: two", "alice: three", "alice: four"]);
+    bob.send("/history many");
+    bob.expect("error: usage: /history [count]");
+
+    alice.send("/join rust");
+    say(&mut alice, "alice", &["in rust"]);
+    bob.send("/join rust");
+    assert_eq!(backlog(&mut bob), ("-- last 1 messages in rust --".to_string(), vec!["alice: in rust".to_string()]));
+}
+
+#[test]
+fn the_history_file_survives_a_restart () {
+    let dir = temp_dir("history-file");
+    let file = dir.join("history");
+    let args = ["--history-file", file.to_str().unwrap()];
+
+    let server = Server::start_with(&[], &args);
+    let mut alice = join(&server, "alice");
+    say(&mut alice, "alice", &["before the restart"]);
+    alice.send("/join rust");
+    say(&mut alice, "alice", &["rust too"]);
+    drop(server);
+
+    let server = Server::start_with(&[], &args);
+    let mut bob = join(&server, "bob");
+    bob.send("/history 50");
+    assert_eq!(backlog(&mut bob), ("-- last 1 messages in lobby --".to_string(),
+                                   vec!["alice: before the restart".to_string()]));
+    bob.send("/join rust");
+    assert_eq!(backlog(&mut bob).1, vec!["alice: rust too"]);
+    say(&mut bob, "bob", &["after the restart"]);
+    drop(server);
+
+    let server = Server::start_with(&[], &args);
+    let mut carol = Peer::connect(server.port("--listen"));
+    assert_eq!(backlog(&mut carol).1, vec!["alice: before the restart"]);
+    carol.send("/join rust");
+    assert_eq!(backlog(&mut carol).1, vec!["alice: rust too", "bob: after the restart"]);
+}
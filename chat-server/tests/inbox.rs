@@ -0,0 +1,83 @@
+mod common;
+
+use std::fs;
+use std::net::TcpStream;
+use std::path::Path;
+
+use common::{Server, Peer, join, temp_dir};
+
+
+fn start (dir: &Path) -> Server {
+    Server::start_with(&[], &[
+        "--accounts-file", dir.join("accounts").to_str().unwrap(),
+        "--inbox-file", dir.join("inbox").to_str().unwrap(),
+    ])
+}
+
+fn register (server: &Server, nick: &str) -> Peer<TcpStream> {
+    let mut peer = join(server, nick);
+    peer.send("/register secret");
+    peer.expect(&format!("registered and logged in as {}", nick));
+    peer
+}
+
+
+#[test]
+fn messages_for_offline_nicknames_wait_for_the_next_login () {
+    let dir = temp_dir("inbox-offline");
+    let server = start(&dir);
+    let mut alice = join(&server, "alice");
+    drop(register(&server, "bob"));
+    alice.expect("bob disconnected");
+
+    alice.run("
+        > /msg bob are you there?
+        bob is offline, the message will be delivered when they log in
+        > /msg nobody hello
+        error: no such nickname nobody
+    ");
+    drop(server);
+
+    // the inbox outlives a restart, and is emptied by reading it
+    let server = start(&dir);
+    let mut bob = Peer::connect(server.port("--listen"));
+    bob.send("/login bob secret");
+    bob.expect("] [alice -> bob] are you there?");
+    bob.expect("logged in as bob");
+    assert_eq!(fs::read_to_string(dir.join("inbox")).unwrap(), "");
+}
+
+#[test]
+fn mentions_are_kept_in_the_inbox () {
+    let dir = temp_dir("inbox-mentions");
+    let server = start(&dir);
+    let mut bob = register(&server, "bob");
+    let mut alice = join(&server, "alice");
+    bob.expect("is now known as alice");
+
+    alice.send("thanks @bob!");
+    alice.send("@carol is not registered");
+    bob.expect("alice: thanks @bob!");
+    bob.expect("alice: @carol is not registered");
+    drop(bob);
+
+    let mut bob = Peer::connect(server.port("--listen"));
+    bob.send("/login bob secret");
+    bob.expect("1 mention in your inbox, see /inbox");
+    bob.expect("logged in as bob");
+    bob.send("/inbox");
+    bob.expect_next("inbox:");
+    bob.expect("] alice in lobby: thanks @bob!");
+    bob.run("
+        > /inbox clear
+        inbox cleared
+        > /inbox
+        your inbox is empty
+    ");
+
+    let mut carol = join(&server, "carol");
+    carol.run("
+        > /inbox
+        error: you need to /register or /login to have an inbox
+    ");
+}
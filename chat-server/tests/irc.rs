@@ -0,0 +1,102 @@
+mod common;
+
+use common::{Server, Peer};
+
+
+#[test]
+fn registration_and_ping () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::connect(server.port("--irc-listen"));
+    alice.send("PRIVMSG #lobby :too early");
+    alice.expect(" 451 * :You have not registered");
+    alice.send("NICK alice");
+    alice.send("USER alice 0 * :Alice Liddell");
+    assert_eq!(alice.expect(" 001 "), ":chat-server 001 alice :Welcome to the chat server alice");
+    alice.send("PING :12345");
+    assert_eq!(alice.expect("PONG"), ":chat-server PONG chat-server :12345");
+}
+
+#[test]
+fn nickname_in_use () {
+    let server = Server::start(&["--irc-listen"]);
+    let _alice = Peer::register(server.port("--irc-listen"), "alice");
+    let mut other = Peer::connect(server.port("--irc-listen"));
+    other.send("NICK alice");
+    other.send("USER alice 0 * :Alice");
+    other.expect(" 433 * alice :Nickname is already in use");
+    other.send("NICK alice2");
+    other.expect(" 001 alice2 ");
+}
+
+#[test]
+fn join_privmsg_names_and_part () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::register(server.port("--irc-listen"), "alice");
+    let mut bob = Peer::register(server.port("--irc-listen"), "bob");
+
+    alice.send("JOIN #rust");
+    assert_eq!(alice.expect("JOIN"), ":alice!alice@chat-server JOIN #rust");
+    alice.expect(" 366 alice #rust ");
+    bob.send("JOIN #rust");
+    bob.expect(" 353 bob = #rust :alice bob");
+    assert_eq!(alice.expect("JOIN"), ":bob!bob@chat-server JOIN #rust");
+
+    alice.send("PRIVMSG #rust :hello bob");
+    assert_eq!(bob.expect("PRIVMSG"), ":alice!alice@chat-server PRIVMSG #rust :hello bob");
+    bob.send("PRIVMSG alice :psst");
+    assert_eq!(alice.expect("PRIVMSG"), ":bob!bob@chat-server PRIVMSG alice :psst");
+
+    alice.send("NAMES #rust");
+    alice.expect(" 353 alice = #rust :alice bob");
+    bob.send("PART #rust");
+    assert_eq!(bob.expect("PART"), ":bob!bob@chat-server PART #rust");
+    assert_eq!(alice.expect("PART"), ":bob!bob@chat-server PART #rust");
+    bob.send("PRIVMSG #rust :still here?");
+    bob.expect(" 404 bob #rust ");
+}
+
+#[test]
+fn quit_is_seen_by_channel_members () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::register(server.port("--irc-listen"), "alice");
+    let mut bob = Peer::register(server.port("--irc-listen"), "bob");
+    alice.send("JOIN #rust");
+    alice.expect(" 366 ");
+    bob.send("JOIN #rust");
+    bob.expect(" 366 ");
+    bob.send("QUIT :bye");
+    assert_eq!(alice.expect("QUIT"), ":bob!bob@chat-server QUIT :disconnected");
+}
+
+#[test]
+fn irc_and_text_clients_share_rooms () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::register(server.port("--irc-listen"), "alice");
+    alice.send("JOIN #lobby");
+    alice.expect(" 366 ");
+
+    let mut telnet = Peer::connect(server.port("--listen"));
+    // the text client is still named after its address, which no nickname may look like
+    let text_name = alice.expect("JOIN #lobby").split('!').next().unwrap()[1..].to_string();
+    assert!(text_name.starts_with("_127_0_0_1_"), "{}", text_name);
+    assert!(text_name[1..].chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "{}", text_name);
+    telnet.send("hi from telnet");
+    assert_eq!(alice.expect("PRIVMSG"),
+               format!(":{}!{}@chat-server PRIVMSG #lobby :hi from telnet", text_name, text_name));
+    alice.send("NAMES #lobby");
+    let names = alice.expect(" 353 alice = #lobby :");
+    assert!(names.split(':').nth(2).unwrap().split(' ').any(|name| name == text_name), "{}", names);
+
+    alice.send("PRIVMSG #lobby :hi from irc");
+    assert_eq!(telnet.expect("hi from irc"), "alice: hi from irc");
+}
+
+#[test]
+fn inbox_takes_only_clear () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::register(server.port("--irc-listen"), "alice");
+    alice.send("INBOX everything");
+    alice.expect(" 461 alice INBOX :Not enough parameters");
+    alice.send("INBOX clear");
+    alice.expect("you need to /register or /login to have an inbox");
+}
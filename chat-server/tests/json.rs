@@ -0,0 +1,77 @@
+extern crate serde_json;
+
+mod common;
+
+use std::net::TcpStream;
+
+use serde_json::Value;
+
+use common::{Server, Peer, join};
+
+
+fn expect_json (peer: &mut Peer<TcpStream>, kind: &str) -> Value {
+    loop {
+        let value: Value = serde_json::from_str(&peer.expect("{")).unwrap();
+        if value["type"] == kind {
+            return value;
+        }
+    }
+}
+
+#[test]
+fn json_clients_chat_with_text_clients () {
+    let server = Server::start(&[]);
+    let mut bot = Peer::connect(server.port("--listen"));
+    bot.send("/protocol json");
+    assert_eq!(expect_json(&mut bot, "notice")["text"], "switched to the json protocol");
+    bot.send(r#"{"type": "nick", "nick": "bot"}"#);
+    let nick = expect_json(&mut bot, "nick");
+    assert_eq!(nick["new"], "bot");
+    assert_eq!(nick["rooms"][0], "lobby");
+
+    let mut human = Peer::connect(server.port("--listen"));
+    human.send("/nick human");
+    let join = expect_json(&mut bot, "join");
+    assert_eq!(join["room"], "lobby");
+    human.send("hello bot");
+    let message = expect_json(&mut bot, "message");
+    assert_eq!(message["name"], "human");
+    assert_eq!(message["room"], "lobby");
+    assert_eq!(message["text"], "hello bot");
+    assert_eq!(message["id"], join["id"]);
+    assert!(message["time"].as_i64().unwrap() > 0);
+
+    bot.send(r#"{"type": "message", "text": "/not a command"}"#);
+    assert_eq!(human.expect("bot:"), "bot: /not a command");
+    bot.send(r#"{"type": "shout"}"#);
+    assert_eq!(expect_json(&mut bot, "error")["text"], "unknown type `shout`");
+    bot.send(r#"{"type": "command", "text": "/join rust"}"#);
+    assert_eq!(expect_json(&mut bot, "join")["room"], "rust");
+    human.expect("bot left lobby");
+}
+
+#[test]
+fn line_breaks_in_json_strings_are_refused () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut irc = Peer::register(server.port("--irc-listen"), "irc");
+    irc.send("JOIN #lobby");
+    irc.expect(" 366 ");
+    let mut text = join(&server, "text");
+    let mut bot = join(&server, "bot");
+    bot.send("/protocol json");
+    expect_json(&mut bot, "notice");
+
+    bot.send(r#"{"type": "message", "text": "hi\nadmin: fake line\r\n:evil!evil@chat-server PRIVMSG #lobby :spoofed"}"#);
+    assert_eq!(expect_json(&mut bot, "error")["text"], "string field `text` may not contain control characters");
+    bot.send(r#"{"type": "message", "to": "text", "text": "psst\nadmin: fake line"}"#);
+    expect_json(&mut bot, "error");
+    bot.send(r#"{"type": "message", "text": "hi"}"#);
+
+    // the message which got through comes as one line, nothing made up follows it
+    assert_eq!(irc.expect("PRIVMSG"), ":bot!bot@chat-server PRIVMSG #lobby :hi");
+    irc.send("PING :done");
+    irc.expect_next(":chat-server PONG chat-server :done");
+    assert_eq!(text.expect("hi"), "bot: hi");
+    text.send("done");
+    text.expect_next("text: done");
+}
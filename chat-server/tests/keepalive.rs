@@ -0,0 +1,37 @@
+mod common;
+
+use common::{Server, Peer};
+
+
+#[test]
+fn quiet_clients_are_pinged () {
+    let server = Server::start_with(&["--irc-listen"], &["--ping-interval", "1", "--idle-timeout", "10"]);
+    let mut text = Peer::connect(server.port("--listen"));
+    text.expect("PING ");
+    let mut irc = Peer::register(server.port("--irc-listen"), "alice");
+    irc.expect("PING :");
+}
+
+#[test]
+fn silent_clients_time_out () {
+    let server = Server::start_with(&[], &["--ping-interval", "1", "--idle-timeout", "3"]);
+    let mut alice = Peer::connect(server.port("--listen"));
+    alice.send("/nick alice");
+    alice.expect("is now known as alice");
+    let mut bob = Peer::connect(server.port("--listen"));
+    bob.send("/nick bob");
+    bob.expect("is now known as bob");
+
+    // alice answers every ping, bob never does
+    loop {
+        let line = alice.expect("");
+        if line == "bob timed out" {
+            break;
+        }
+        if let Some(token) = line.strip_prefix("PING ") {
+            alice.send(&format!("/pong {}", token));
+        }
+    }
+    bob.expect("timed out");
+    bob.expect_closed();
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{Server, Peer, connect};
+
+
+#[test]
+fn flooding_client_is_warned_then_disconnected () {
+    let server = Server::start_with(&[], &["--flood-messages", "1", "--flood-burst", "1",
+                                           "--flood-warnings", "1"]);
+    let mut flooder = Peer::connect(server.port("--listen"));
+    flooder.send("one");
+    flooder.send("two");
+    flooder.expect("you are sending too fast, slow down");
+    flooder.send("three");
+    flooder.expect("disconnected for flooding");
+    flooder.expect_closed();
+}
+
+#[test]
+fn connections_per_address_are_limited () {
+    let server = Server::start_with(&[], &["--max-clients-per-ip", "2"]);
+    let _first = Peer::connect(server.port("--listen"));
+    let _second = Peer::connect(server.port("--listen"));
+    let mut third = Peer::new(connect(server.port("--listen")));
+    assert_eq!(third.expect("error"), "error: too many connections from 127.0.0.1");
+}
+
+#[test]
+fn a_client_which_stops_reading_holds_up_nobody_else () {
+    let server = Server::start_with(&[], &["--flood-messages", "0", "--flood-bytes", "0"]);
+    let mut bob = Peer::connect(server.port("--listen"));
+    bob.send("/nick bob");
+    bob.expect("is now known as bob");
+    let mut alice = connect(server.port("--listen"));
+
+    // alice reads everything on another thread and says when bob is gone
+    let (gone_tx, gone) = mpsc::channel();
+    let mut reader = BufReader::new(alice.try_clone().unwrap());
+    thread::spawn(move || {
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            if line.trim_end() == "bob disconnected" {
+                let _ = gone_tx.send(());
+            }
+            line.clear();
+        }
+    });
+    // far more than the socket buffers and bob's outbox hold, while bob reads nothing
+    let line = format!("{}\n", "x".repeat(1000));
+    for _ in 0..20_000 {
+        alice.write_all(line.as_bytes()).unwrap();
+    }
+
+    let started = Instant::now();
+    let mut carol = Peer::connect(server.port("--listen"));
+    carol.expect("connected");
+    assert!(started.elapsed() < Duration::from_secs(3));
+    gone.recv_timeout(Duration::from_secs(15)).expect("bob was never dropped");
+}
+
+#[test]
+fn overlong_lines_end_the_connection () {
+    let server = Server::start_with(&["--irc-listen"], &["--flood-bytes", "0"]);
+    let mut alice = Peer::connect(server.port("--listen"));
+    alice.send(&"x".repeat(60 * 1024));
+    alice.expect(&"x".repeat(60 * 1024));
+
+    // without a newline, and far more than a line may be
+    for option in ["--listen", "--irc-listen"].iter() {
+        let mut stream = connect(server.port(option));
+        let chunk = vec![b'x'; 64 * 1024];
+        for _ in 0..100 {
+            if stream.write_all(&chunk).is_err() {
+                break;
+            }
+        }
+        // the server may hang up before reading everything, which resets the connection
+        let mut rest = Vec::new();
+        if let Err(e) = stream.read_to_end(&mut rest) {
+            assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+        }
+    }
+}
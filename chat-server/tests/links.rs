@@ -0,0 +1,191 @@
+mod common;
+
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{Server, Peer, create_account, join, temp_dir};
+
+
+fn start (name: &str, links: &[&Server]) -> Server {
+    let links: Vec<String> = links.iter().map(|server| format!("127.0.0.1:{}", server.port("--link-listen"))).collect();
+    let links: Vec<&str> = links.iter().map(String::as_str).collect();
+    start_with(name, &links, &[])
+}
+
+// a server called `name` linking to `links`, with a link listener unless `extra` has one
+fn start_with (name: &str, links: &[&str], extra: &[&str]) -> Server {
+    let mut args = vec!["--server-name", name];
+    for link in links {
+        args.extend(["--link", link]);
+    }
+    args.extend(extra);
+    let listeners: &[&str] = if extra.contains(&"--link-listen") { &[] } else { &["--link-listen"] };
+    let server = Server::start_with(listeners, &args);
+    // the server is up once it accepts clients
+    Peer::connect(server.port("--listen"));
+    server
+}
+
+// asks for the names in the room until they are all there
+fn wait_for (peer: &mut Peer<TcpStream>, names: &str) {
+    let mut line = String::new();
+    for _ in 0..40 {
+        peer.send("/names");
+        line = peer.expect("names in ");
+        if line.ends_with(names) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    panic!("still {:?} while waiting for {:?}", line, names);
+}
+
+
+#[test]
+fn messages_and_presence_are_relayed_both_ways () {
+    let east = start("east", &[]);
+    let west = start("west", &[&east]);
+    let mut alice = join(&east, "alice");
+    let mut bob = join(&west, "bob");
+    wait_for(&mut alice, ": alice bob");
+
+    alice.send("hi from the east");
+    bob.expect("alice: hi from the east");
+    bob.send("hi from the west");
+    alice.expect("bob: hi from the west");
+
+    bob.send("/join rust");
+    alice.expect("bob left lobby");
+    alice.send("/join rust");
+    alice.expect("alice joined rust");
+    bob.expect("alice joined rust");
+
+    // the away status travels on its own, the message shows it has arrived
+    bob.send("/away lunch");
+    bob.send("brb");
+    alice.expect("bob: brb");
+    alice.send("/msg bob back soon?");
+    alice.expect("bob is away: lunch");
+    bob.expect("[alice -> bob] back soon?");
+    alice.send("/who");
+    alice.expect("bob is away: lunch");
+
+    bob.send("/nick robert");
+    alice.expect("bob is now known as robert");
+    drop(bob);
+    alice.expect("robert disconnected");
+    alice.run("
+        > /names
+        names in rust: alice
+    ");
+}
+
+#[test]
+fn taken_nicknames_get_the_server_name () {
+    let east = start_with("east", &[], &["--plugin", "dice"]);
+    let west = start("west", &[]);
+    let mut east_alice = join(&east, "alice");
+    let mut carol = join(&east, "carol");
+    let mut west_alice = join(&west, "alice");
+    let mut bob = join(&west, "bob");
+    // everyone is there before the servers are linked
+    let _hub = start("hub", &[&east, &west]);
+    wait_for(&mut east_alice, ": alice alice@west bob carol");
+    wait_for(&mut west_alice, ": alice alice@east bob carol");
+
+    east_alice.send("/msg alice@west hello me");
+    west_alice.expect("[alice@east -> alice] hello me");
+    west_alice.send("/msg carol hi");
+    carol.expect("[alice@west -> carol] hi");
+
+    carol.send("/nick bob");
+    carol.expect("error: nickname bob is already in use");
+    // only east knows of its plugins
+    bob.send("/nick dice");
+    east_alice.expect("bob is now known as dice@west");
+    west_alice.expect("bob is now known as dice");
+}
+
+#[test]
+fn registered_nicknames_are_kept_from_remote_users () {
+    let dir = temp_dir("links-accounts");
+    let accounts = dir.join("accounts");
+    create_account(&accounts, "alice", "secret");
+    let east = start_with("east", &[], &["--accounts-file", accounts.to_str().unwrap()]);
+    let west = start("west", &[&east]);
+    let mut bob = join(&east, "bob");
+    let _alice = join(&west, "alice");
+    wait_for(&mut bob, ": alice@west bob");
+
+    // the message waits for the alice registered here
+    bob.send("/msg alice are you there?");
+    bob.expect("alice is offline, the message will be delivered when they log in");
+    let mut alice = Peer::connect(east.port("--listen"));
+    alice.send("/login alice secret");
+    alice.expect("[bob -> alice] are you there?");
+    alice.expect("logged in as alice");
+}
+
+#[test]
+fn a_link_which_stops_reading_holds_up_nobody () {
+    let east = start_with("east", &[], &["--flood-messages", "0", "--flood-bytes", "0"]);
+    // a server which says hello and then never reads
+    let mut stalled = common::connect(east.port("--link-listen"));
+    stalled.write_all(b"{\"type\": \"hello\", \"name\": \"stalled\"}\n").unwrap();
+    let mut alice = join(&east, "alice");
+
+    // far more than fits in the socket buffers on the way to the stalled link
+    let long = "x".repeat(60 * 1024);
+    let start = Instant::now();
+    for _ in 0..200 {
+        alice.send(&long);
+        alice.expect(&long);
+    }
+    // waiting on the link would have held everyone up for the write timeout
+    assert!(start.elapsed() < Duration::from_secs(10), "took {:?}", start.elapsed());
+}
+
+#[test]
+fn servers_form_a_tree_and_split_cleanly () {
+    let east = start("east", &[]);
+    let mut alice = join(&east, "alice");
+    // north tries west before it is there, and keeps trying
+    let west_links = common::free_port();
+    let north = start_with("north", &[&format!("127.0.0.1:{}", east.port("--link-listen")),
+                                      &format!("127.0.0.1:{}", west_links)], &[]);
+    let mut carol = join(&north, "carol");
+    let west = start_with("west", &[&format!("127.0.0.1:{}", east.port("--link-listen"))],
+                          &["--link-listen", &format!("127.0.0.1:{}", west_links)]);
+    let mut bob = join(&west, "bob");
+    wait_for(&mut alice, ": alice bob carol");
+    wait_for(&mut carol, ": alice bob carol");
+
+    // a name already in the network is turned away
+    let impostor = start("west", &[&east]);
+    let _eve = join(&impostor, "eve");
+
+    // by now north has tried west again and been refused, as west is already
+    // reached through east, so everything still arrives once
+    thread::sleep(Duration::from_secs(6));
+    carol.send("one");
+    bob.send("two");
+    let mut lines = vec![alice.expect(": "), alice.expect(": ")];
+    lines.sort();
+    assert_eq!(lines, vec!["bob: two", "carol: one"]);
+    alice.run("
+        > three
+        alice: three
+        > /names
+        names in lobby: alice bob carol
+    ");
+
+    drop(west);
+    alice.expect_next("bob left in a netsplit");
+    carol.expect("bob left in a netsplit");
+    carol.run("
+        > /names
+        names in lobby: alice carol
+    ");
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::io;
+use std::io::prelude::*;
+
+use common::{Server, Peer, create_account, join, temp_dir};
+
+
+fn get (port: u16, path: &str) -> String {
+    let mut stream = common::connect(port);
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+
+#[test]
+fn metrics_are_served_in_prometheus_format () {
+    let server = Server::start_with(&["--metrics-listen"], &[]);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+    alice.send("hello");
+    bob.expect("alice: hello");
+
+    let response = get(server.port("--metrics-listen"), "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("# TYPE chat_clients gauge\nchat_clients 2\n"));
+    assert!(response.contains("# TYPE chat_messages_total counter\nchat_messages_total 1\n"));
+    assert!(response.contains("chat_connections_accepted_total 2\n"));
+    assert!(response.contains("chat_rooms 1\n"));
+
+    assert!(get(server.port("--metrics-listen"), "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    // a request line which never ends is not buffered whole, nor answered
+    let mut endless = common::connect(server.port("--metrics-listen"));
+    endless.write_all(&vec![b'x'; 64 * 1024 + 1]).unwrap();
+    let mut response = String::new();
+    // the server may hang up before reading everything, which resets the connection
+    if let Err(e) = endless.read_to_string(&mut response) {
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+    }
+    assert_eq!(response, "");
+}
+
+#[test]
+fn stats_are_for_operators () {
+    let dir = temp_dir("stats");
+    let accounts = dir.join("accounts");
+    create_account(&accounts, "alice", "secret");
+    let server = Server::start_with(&[], &[
+        "--accounts-file", accounts.to_str().unwrap(),
+        "--operator", "alice",
+    ]);
+    let mut bob = join(&server, "bob");
+    let mut alice = Peer::connect(server.port("--listen"));
+
+    bob.send("/stats");
+    bob.expect("error: you are not an operator");
+
+    alice.send("/login alice secret");
+    alice.expect("logged in as alice");
+    alice.send("/stats");
+    assert_eq!(alice.expect("Clients which"), "Clients which have entered the chat: 2");
+    assert_eq!(alice.expect("Connections accepted"), "Connections accepted: 2");
+}
@@ -0,0 +1,92 @@
+extern crate chat_server;
+
+mod common;
+
+use std::net::TcpStream;
+use std::path::Path;
+
+use common::{Server, Peer, create_account, join, temp_dir};
+
+
+fn start_moderated_server (dir: &Path) -> Server {
+    if !dir.join("accounts").exists() {
+        create_account(&dir.join("accounts"), "alice", "secret");
+    }
+    Server::start_with(&[], &[
+        "--accounts-file", dir.join("accounts").to_str().unwrap(),
+        "--bans-file", dir.join("bans").to_str().unwrap(),
+        "--operator", "alice",
+    ])
+}
+
+fn operator (server: &Server) -> Peer<TcpStream> {
+    let mut alice = Peer::connect(server.port("--listen"));
+    alice.send("/login alice secret");
+    alice.expect("logged in as alice");
+    alice
+}
+
+
+#[test]
+fn operators_mute_and_kick () {
+    let dir = temp_dir("mute-kick");
+    let server = start_moderated_server(&dir);
+    let mut alice = operator(&server);
+    let mut bob = join(&server, "bob");
+
+    bob.send("/kick alice");
+    bob.expect("error: you are not an operator");
+    bob.send("/mute alice 999999999999999999d");
+    bob.expect("error: invalid duration 999999999999999999d");
+    bob.send("/ban alice 999999999999999999d");
+    bob.expect("error: you are not an operator");
+
+    alice.send("/mute bob 200000000000000d");
+    alice.expect("error: duration too long");
+
+    alice.send("/mute bob");
+    bob.expect("you have been muted by alice");
+    bob.send("can you hear me?");
+    bob.expect("error: you are muted");
+    alice.send("/unmute bob");
+    bob.expect("you are no longer muted");
+    bob.send("hello again");
+    alice.expect("bob: hello again");
+
+    alice.send("/kick bob go away");
+    bob.expect("kicked by alice: go away");
+    bob.expect_closed();
+    alice.expect("bob disconnected");
+}
+
+#[test]
+fn bans_are_enforced_and_persisted () {
+    let dir = temp_dir("bans");
+    let server = start_moderated_server(&dir);
+    let mut alice = operator(&server);
+
+    alice.send("/ban 127.0.0.1 1h spam");
+    alice.expect("banned by alice: spam");
+    alice.expect_closed();
+    let mut refused = Peer::connect(server.port("--listen"));
+    refused.expect("error: you are banned from this server: spam");
+    drop(server);
+
+    let server = start_moderated_server(&dir);
+    let mut refused = Peer::connect(server.port("--listen"));
+    refused.expect("error: you are banned from this server: spam");
+    refused.expect_closed();
+}
+
+#[test]
+fn operators_must_be_registered_beforehand () {
+    let dir = temp_dir("unregistered-operator");
+    let accounts = dir.join("accounts");
+    let args = ["--listen", "127.0.0.1:0", "--accounts-file", accounts.to_str().unwrap(), "--operator", "alice"];
+    let config = chat_server::Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+    match chat_server::start(config) {
+        Err(e) => assert_eq!(e, "operator alice is not registered, start the server without --operator \
+                                 and /register it first"),
+        Ok(_) => panic!("the server started with an unregistered operator"),
+    }
+}
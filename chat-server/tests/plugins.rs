@@ -0,0 +1,71 @@
+mod common;
+
+use std::fs;
+
+use common::{Server, join, temp_dir};
+
+
+
+#[test]
+fn dice_and_reminders_answer_in_the_room () {
+    let server = Server::start_with(&[], &["--plugin", "dice", "--plugin", "reminders"]);
+    let mut alice = join(&server, "alice");
+
+    alice.send("/nick dice");
+    alice.expect("error: nickname dice is already in use");
+    alice.send("!roll 3d1");
+    assert_eq!(alice.expect("dice:"), "dice: alice rolled 3d1: 1 + 1 + 1 = 3");
+
+    alice.send("!remind 1s stretch");
+    assert_eq!(alice.expect("reminders:"), "reminders: alice: I will remind you in 1s");
+    assert_eq!(alice.expect("reminders:"), "reminders: alice: reminder: stretch");
+
+    alice.send("/msg reminders !remind 1s tea");
+    assert_eq!(alice.expect("[reminders -> alice]"), "[reminders -> alice] I will remind you in 1s");
+    assert_eq!(alice.expect("[reminders -> alice]"), "[reminders -> alice] reminder: tea");
+}
+
+#[test]
+fn reminders_too_far_ahead_are_refused () {
+    let server = Server::start_with(&[], &["--plugin", "reminders"]);
+    let mut alice = join(&server, "alice");
+
+    alice.send("!remind 150000000000000d tea");
+    assert_eq!(alice.expect("reminders:"),
+               "reminders: alice: reminder too far in the future, at most 30d");
+    alice.send("!remind 31d tea");
+    assert_eq!(alice.expect("reminders:"),
+               "reminders: alice: reminder too far in the future, at most 30d");
+    alice.send("!remind 30d tea");
+    assert_eq!(alice.expect("reminders:"), "reminders: alice: I will remind you in 30d");
+}
+
+#[test]
+fn links_are_previewed_from_the_cache () {
+    let dir = temp_dir("links");
+    let cache = dir.join("cache");
+    fs::write(&cache, "https://example.com/\tExample Domain\n").unwrap();
+    let server = Server::start_with(&[], &["--plugin", "links", "--link-cache", cache.to_str().unwrap()]);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    alice.send("have a look at https://example.com/ and https://unknown.example/");
+    assert_eq!(bob.expect("links:"), "links: [link] Example Domain");
+    alice.send("the preview comes after the message");
+    assert_eq!(bob.expect("the preview"), "alice: the preview comes after the message");
+}
+
+#[test]
+fn filter_mutes_through_a_command () {
+    let dir = temp_dir("filter");
+    let words = dir.join("words");
+    fs::write(&words, "heck\n").unwrap();
+    let server = Server::start_with(&[], &["--plugin", "filter", "--filter-words", words.to_str().unwrap()]);
+    let mut bob = join(&server, "bob");
+
+    bob.send("what the Heck!");
+    bob.expect("you have been muted by filter");
+    assert_eq!(bob.expect("filter:"), "filter: bob is muted for 1m, mind your language");
+    bob.send("sorry");
+    bob.expect("error: you are muted");
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{Server, Peer, join};
+
+
+
+#[test]
+fn away_status_shows_in_who_and_answers_private_messages () {
+    let server = Server::start(&[]);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    bob.send("/away gone for lunch");
+    bob.expect("you are marked as away, /back when you return");
+    alice.send("/msg bob are you there?");
+    alice.expect("bob is away: gone for lunch");
+    bob.expect("[alice -> bob] are you there?");
+
+    alice.send("/who");
+    alice.expect("who in lobby:");
+    assert_eq!(alice.expect("alice is"), "alice is here, idle 0s");
+    assert_eq!(alice.expect("bob is"), "bob is away: gone for lunch, idle 0s");
+
+    bob.send("/back");
+    bob.expect("welcome back");
+    bob.send("/back");
+    bob.expect("error: you are not away");
+    alice.send("/who lobby");
+    alice.expect("bob is here");
+}
+
+#[test]
+fn irc_clients_see_away_replies_and_who_flags () {
+    let server = Server::start(&["--irc-listen"]);
+    let mut alice = Peer::register(server.port("--irc-listen"), "alice");
+    let mut bob = join(&server, "bob");
+    alice.send("JOIN #lobby");
+    alice.expect(" 366 ");
+
+    bob.send("/away");
+    bob.expect("you are marked as away");
+    alice.send("PRIVMSG bob :hi");
+    assert_eq!(alice.expect(" 301 "), ":chat-server 301 alice bob :Away");
+
+    alice.send("AWAY :meeting");
+    alice.expect(" 306 ");
+    alice.send("WHO #lobby");
+    assert_eq!(alice.expect(" 352 "), ":chat-server 352 alice #lobby alice chat-server chat-server alice G :0 alice");
+    assert_eq!(alice.expect(" 352 "), ":chat-server 352 alice #lobby bob chat-server chat-server bob G :0 bob");
+    alice.expect(" 315 ");
+    bob.send("/msg alice ping");
+    bob.expect("alice is away: meeting");
+}
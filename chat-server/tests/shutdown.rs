@@ -0,0 +1,23 @@
+mod common;
+
+use common::{Server, Peer};
+
+
+#[test]
+fn clients_are_told_before_the_server_exits () {
+    let mut server = Server::start(&["--irc-listen"]);
+    let mut text = Peer::connect(server.port("--listen"));
+    text.send("/nick alice");
+    text.expect("is now known as alice");
+    let mut irc = Peer::register(server.port("--irc-listen"), "bob");
+    irc.send("JOIN #lobby");
+    irc.expect(" 366 ");
+
+    irc.send("PRIVMSG #lobby :last words");
+    text.expect("bob: last words");
+    assert!(server.terminate().success());
+    text.expect("the server is shutting down");
+    text.expect_closed();
+    irc.expect("ERROR :Closing link: the server is shutting down");
+    irc.expect_closed();
+}
@@ -0,0 +1,89 @@
+extern crate chat_server;
+extern crate rcgen;
+extern crate rustls;
+
+mod common;
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use rustls::pki_types::ServerName;
+
+use common::{Server, Peer, connect, temp_dir};
+
+
+// writes a fresh self-signed certificate for `localhost` into `dir`,
+// returning the certificate and key paths plus the certificate itself
+fn self_signed (dir: &Path) -> (PathBuf, PathBuf, rcgen::CertifiedKey) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    fs::write(&cert_path, certified.cert.pem()).unwrap();
+    fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+    (cert_path, key_path, certified)
+}
+
+fn start_tls_server (cert: &Path, key: &Path) -> Server {
+    Server::start_with(&["--tls-listen"], &[
+        "--tls-cert", cert.to_str().unwrap(),
+        "--tls-key", key.to_str().unwrap(),
+    ])
+}
+
+fn tls_stream (port: u16, certified: &rcgen::CertifiedKey) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots.add(certified.cert.der().clone()).unwrap();
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let name = ServerName::try_from("localhost").unwrap();
+    let connection = ClientConnection::new(Arc::new(config), name).unwrap();
+    StreamOwned::new(connection, connect(port))
+}
+
+
+#[test]
+fn tls_and_plaintext_clients_chat () {
+    let dir = temp_dir("tls-chat");
+    let (cert, key, certified) = self_signed(&dir);
+    let server = start_tls_server(&cert, &key);
+
+    let mut secure = Peer::new(tls_stream(server.port("--tls-listen"), &certified));
+    secure.send("/nick secure");
+    secure.expect("is now known as secure");
+
+    let mut plain = Peer::connect(server.port("--listen"));
+    plain.send("/nick plain");
+    secure.expect("is now known as plain");
+
+    secure.send("over tls");
+    assert_eq!(plain.expect("over tls"), "secure: over tls");
+    plain.send("in the clear");
+    assert_eq!(secure.expect("in the clear"), "plain: in the clear");
+}
+
+#[test]
+fn untrusted_certificate_is_rejected () {
+    let (dir, other_dir) = (temp_dir("tls-untrusted"), temp_dir("tls-other"));
+    let (cert, key, _) = self_signed(&dir);
+    let (_, _, other) = self_signed(&other_dir);
+    let server = start_tls_server(&cert, &key);
+
+    let mut client = tls_stream(server.port("--tls-listen"), &other);
+    let mut buffer = [0; 64];
+    assert!(client.read(&mut buffer).is_err());
+}
+
+#[test]
+fn certificates_and_the_tls_listener_come_together () {
+    let config = |args: &[&str]| chat_server::Config::from_args(args.iter().map(|arg| arg.to_string())).err();
+    assert_eq!(config(&["--tls-listen", "127.0.0.1:0", "--tls-cert", "cert.pem"]),
+               Some("--tls-listen needs both --tls-cert and --tls-key".to_string()));
+    for args in [["--tls-cert", "cert.pem"], ["--tls-key", "key.pem"]].iter() {
+        assert_eq!(config(args), Some("--tls-cert and --tls-key need a --tls-listen".to_string()));
+    }
+    assert_eq!(config(&["--tls-cert", "cert.pem", "--tls-key", "key.pem"]),
+               Some("--tls-cert and --tls-key need a --tls-listen".to_string()));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::fs;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use common::{Server, Peer, connect, temp_dir};
+
+
+// the transcripts of a room, all days together
+fn transcript (dir: &Path, room: &str) -> String {
+    let mut days: Vec<PathBuf> = fs::read_dir(dir.join(room)).unwrap().map(|e| e.unwrap().path()).collect();
+    days.sort();
+    days.iter().map(|day| fs::read_to_string(day).unwrap()).collect()
+}
+
+// drops the [hh:mm:ss] in front of every line
+fn without_times (text: &str) -> Vec<String> {
+    text.lines().map(|line| line.split_once("] ").unwrap().1.to_string()).collect()
+}
+
+
+#[test]
+fn rooms_are_transcribed_and_logged_without_addresses () {
+    let dir = temp_dir("transcripts");
+    let server = Server::start_with(&[], &[
+        "--transcript-dir", dir.to_str().unwrap(),
+        "--hide-addresses", "true",
+        "--max-clients-per-ip", "1",
+    ]);
+    let mut alice: Peer<TcpStream> = Peer::connect(server.port("--listen"));
+    alice.send("/nick alice");
+    alice.expect("is now known as alice");
+    alice.send("hello");
+    alice.expect("alice: hello");
+    alice.send("/join ../etc");
+    alice.send("/msg alice private");
+    alice.expect("[alice -> alice] private");
+    alice.send("anyone?");
+    alice.expect("alice: anyone?");
+    // a second client from the same address is turned away, without the address being logged
+    drop(connect(server.port("--listen")));
+    assert!(server.expect_log("connection refused").contains("reason=\"too many connections from -\""));
+    for line in server.log().iter().filter(|line| !line.contains("msg=listening")) {
+        assert!(!line.contains("127.0.0.1"), "{}", line);
+    }
+    // killed before alice leaves, so her departure is not recorded
+    drop(server);
+
+    assert_eq!(without_times(&transcript(&dir, "lobby")), vec![
+        "- connected",
+        "- is now known as alice",
+        "alice: hello",
+        "alice left lobby",
+    ]);
+    assert_eq!(without_times(&transcript(&dir, "%2E%2E%2Fetc")), vec![
+        "alice joined ../etc",
+        "alice: anyone?",
+    ]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Server, join, temp_dir};
+
+
+fn start_server (spool: &Path) -> Server {
+    Server::start_with(&[], &["--spool-dir", spool.to_str().unwrap(), "--max-file-size", "10"])
+}
+
+
+#[test]
+fn files_are_uploaded_offered_and_downloaded () {
+    let spool = temp_dir("transfer");
+    let server = start_server(&spool);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    alice.send("/send bob notes.txt 11");
+    alice.expect("error: notes.txt is too large, files may have at most 10 bytes");
+    alice.send("/send bob ../notes.txt 5");
+    assert_eq!(alice.expect("upload"), "upload 1 ready: send notes.txt with /chunk 1 <offset> <base64>");
+
+    // "hello" in two chunks, the second one first by mistake
+    alice.send("/chunk 1 3 bG8=");
+    alice.expect("error: upload 1 continues at offset 0");
+    alice.send("/chunk 1 0 aGVs");
+    alice.send("/chunk 1 3 bG8=");
+    alice.expect("sent notes.txt to bob, waiting for them to accept");
+    assert_eq!(bob.expect("offers"), "alice offers you notes.txt (5 bytes), /accept 1 or /decline 1");
+
+    bob.send("/download 1");
+    bob.expect("error: use /accept 1 first");
+    bob.send("/accept 1");
+    alice.expect("bob accepted notes.txt");
+    assert_eq!(bob.expect("file"), "file 1 5 notes.txt");
+    assert_eq!(bob.expect("chunk"), "chunk 1 0 aGVsbG8=");
+    assert_eq!(bob.expect("end"), "end 1");
+
+    bob.send("/decline 1");
+    bob.expect("declined file 1");
+    alice.expect("bob declined notes.txt");
+    bob.send("/download 1");
+    bob.expect("error: no file 1");
+}
+
+#[test]
+fn unfinished_uploads_are_dropped_with_their_sender () {
+    let spool = temp_dir("abandon");
+    let server = start_server(&spool);
+    let mut alice = join(&server, "alice");
+    let mut bob = join(&server, "bob");
+
+    alice.send("/send bob a.txt 2");
+    alice.expect("upload 1 ready");
+    alice.send("/chunk 1 0 YQ==");
+    drop(alice);
+    bob.expect("alice disconnected");
+
+    let mut alice = join(&server, "alice");
+    alice.send("/chunk 1 1 YQ==");
+    alice.expect("error: no upload 1");
+    bob.send("/accept 1");
+    bob.expect("error: no file 1");
+}
+
+#[test]
+fn only_leftover_transfers_are_cleared_from_the_spool () {
+    let spool = temp_dir("spool-leftovers");
+    fs::write(spool.join("notes.txt"), "keep me").unwrap();
+    fs::write(spool.join("3"), "left by a previous run").unwrap();
+    let server = start_server(&spool);
+    // once a client is in, the spool has been opened
+    join(&server, "alice");
+
+    assert_eq!(fs::read_to_string(spool.join("notes.txt")).unwrap(), "keep me");
+    assert!(!spool.join("3").exists());
+}
@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use common::{Server, Peer, join, temp_dir};
+
+
+// the server takes a moment to start listening
+fn connect (path: &Path) -> UnixStream {
+    for _ in 0..50 {
+        if let Ok(stream) = UnixStream::connect(path) {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("server did not start on {}", path.display());
+}
+
+
+#[test]
+fn unix_and_tcp_clients_chat_over_a_stale_socket_file () {
+    let dir = temp_dir("unix");
+    let path = dir.join("chat.sock");
+    // what a crashed server leaves behind: the file, with nobody listening
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let mut server = Server::start_with(&[], &["--unix", path.to_str().unwrap()]);
+    let mut local = Peer::new(connect(&path));
+    local.send("/nick local");
+    local.expect("is now known as local");
+    let mut remote = join(&server, "remote");
+
+    local.send("hello over the socket");
+    assert_eq!(remote.expect("hello over"), "local: hello over the socket");
+    remote.send("hello over tcp");
+    assert_eq!(local.expect("hello over tcp"), "remote: hello over tcp");
+
+    // a socket somebody is answering on is left alone
+    let status = Command::new(env!("CARGO_BIN_EXE_chat-server"))
+        .args(["--unix", path.to_str().unwrap()])
+        .stdout(Stdio::null()).stderr(Stdio::null())
+        .status().unwrap();
+    assert!(!status.success());
+    local.send("still here");
+    local.expect("local: still here");
+
+    assert!(server.terminate().success());
+    assert!(!path.exists());
+}
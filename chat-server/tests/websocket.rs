@@ -0,0 +1,166 @@
+mod common;
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+
+use common::{Server, connect, join};
+
+
+// the handshake example of RFC 6455
+const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+const MASK: [u8; 4] = [0x37, 0xFA, 0x21, 0x3D];
+
+// a text frame as a browser sends it, `length` may claim more than `payload`
+fn masked_frame (length: u64, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    if length < 126 {
+        frame.push(0x80 | length as u8);
+    } else if length <= 0xFFFF {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(length as u16).to_be_bytes());
+    } else {
+        frame.push(0x80 | 127);
+        frame.extend_from_slice(&length.to_be_bytes());
+    }
+    frame.extend_from_slice(&MASK);
+    frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ MASK[i % 4]));
+    frame
+}
+
+// reads whatever is left until the server hangs up
+fn expect_closed<R: Read> (reader: &mut R) {
+    let mut rest = Vec::new();
+    // the server may hang up before reading everything, which resets the connection
+    if let Err(e) = reader.read_to_end(&mut rest) {
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+    }
+}
+
+struct WebSocket {
+    reader: BufReader<TcpStream>,
+}
+
+impl WebSocket {
+    // upgrades a plain connection by hand
+    fn connect (port: u16) -> WebSocket {
+        let mut stream = connect(port);
+        write!(stream, "GET /chat HTTP/1.1\r\n\
+                        Host: localhost\r\n\
+                        Upgrade: websocket\r\n\
+                        Connection: Upgrade\r\n\
+                        Sec-WebSocket-Key: {}\r\n\
+                        Sec-WebSocket-Version: 13\r\n\r\n", KEY).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut response = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            response.push(line.trim_end().to_string());
+        }
+        assert_eq!(response[0], "HTTP/1.1 101 Switching Protocols");
+        assert!(response.contains(&format!("Sec-WebSocket-Accept: {}", ACCEPT)));
+        WebSocket { reader }
+    }
+
+    fn send (&mut self, text: &str) {
+        let frame = masked_frame(text.len() as u64, text.as_bytes());
+        self.reader.get_mut().write_all(&frame).unwrap();
+    }
+
+    fn send_raw (&mut self, bytes: &[u8]) {
+        self.reader.get_mut().write_all(bytes).unwrap();
+    }
+
+    // the payload of the next text frame; the server never masks nor sends long lines here
+    fn next_text (&mut self) -> String {
+        let mut header = [0; 2];
+        self.reader.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        assert_eq!(header[1] & 0x80, 0);
+        let length = match header[1] {
+            126 => {
+                let mut length = [0; 2];
+                self.reader.read_exact(&mut length).unwrap();
+                u16::from_be_bytes(length) as usize
+            },
+            length => length as usize,
+        };
+        let mut payload = vec![0; length];
+        self.reader.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+
+    // reads text frames until one contains `pattern`, returning that one
+    fn expect (&mut self, pattern: &str) -> String {
+        loop {
+            let text = self.next_text();
+            if text.contains(pattern) {
+                return text;
+            }
+        }
+    }
+
+    fn expect_closed (&mut self) {
+        expect_closed(&mut self.reader);
+    }
+}
+
+
+#[test]
+fn websocket_and_plaintext_clients_chat () {
+    let server = Server::start(&["--ws-listen"]);
+    let mut plain = join(&server, "plain");
+    let mut browser = WebSocket::connect(server.port("--ws-listen"));
+
+    browser.send("/nick browser");
+    browser.expect("is now known as browser");
+    browser.send("hello from the browser");
+    assert_eq!(plain.expect("hello from the browser"), "browser: hello from the browser");
+
+    plain.send("hello browser");
+    assert_eq!(browser.expect("hello browser"), "plain: hello browser");
+}
+
+#[test]
+fn oversized_and_unmasked_frames_are_refused () {
+    let server = Server::start_with(&["--ws-listen"], &["--flood-bytes", "0"]);
+
+    let mut oversized = WebSocket::connect(server.port("--ws-listen"));
+    oversized.send_raw(&masked_frame(64 * 1024 + 1, b"x"));
+    oversized.expect_closed();
+
+    let mut unmasked = WebSocket::connect(server.port("--ws-listen"));
+    unmasked.send_raw(&[0x81, 5]);
+    unmasked.send_raw(b"hello");
+    unmasked.expect_closed();
+
+    // a frame right at the limit is still fine
+    let mut plain = join(&server, "plain");
+    let mut browser = WebSocket::connect(server.port("--ws-listen"));
+    let long = "x".repeat(64 * 1024);
+    browser.send(&long);
+    assert!(plain.expect(&long).ends_with(&format!(": {}", long)));
+}
+
+#[test]
+fn endless_handshakes_are_refused () {
+    let server = Server::start(&["--ws-listen"]);
+
+    let mut endless_line = connect(server.port("--ws-listen"));
+    endless_line.write_all(b"GET /chat HTTP/1.1\r\nX-Padding: ").unwrap();
+    endless_line.write_all(&vec![b'x'; 64 * 1024 + 1]).unwrap();
+    expect_closed(&mut endless_line);
+
+    let mut endless_headers = connect(server.port("--ws-listen"));
+    endless_headers.write_all(b"GET /chat HTTP/1.1\r\n").unwrap();
+    for _ in 0..101 {
+        endless_headers.write_all(b"X-Padding: x\r\n").unwrap();
+    }
+    expect_closed(&mut endless_headers);
+}
@@ -0,0 +1,221 @@
+use time::Tm;
+
+
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const FULL_DAY_NAMES: [&str; 7] = ["sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday"];
+
+// a time of day, on the given weekdays or just once
+pub struct Alarm {
+    pub hour: i32,
+    pub minute: i32,
+    // indexed like `tm_wday`, from Sunday; None for an alarm which rings once
+    days: Option<[bool; 7]>,
+    // the day it last rang, as (year, day of the year)
+    rang: Option<(i32, i32)>,
+}
+
+impl Alarm {
+    // `7:30`, `19:00` or `6:45pm`, followed by days such as `mon-fri`,
+    // `sat,sun`, `weekdays`, `weekends` or `daily`
+    pub fn parse (text: &str) -> Result<Alarm, String> {
+        let mut words = text.split_whitespace();
+        let (hour, minute) = words.next().and_then(parse_time)
+            .ok_or(format!("invalid alarm `{}`, expected a time such as 7:30 or 6:45pm", text))?;
+        let days = match words.next() {
+            Some(days) => Some(parse_days(days).ok_or(format!("invalid days `{}` for an alarm", days))?),
+            None => None,
+        };
+        if words.next().is_some() {
+            return Err(format!("invalid alarm `{}`", text));
+        }
+        Ok(Alarm { hour, minute, days, rang: None })
+    }
+
+    // true once on each day the alarm is set for, when its minute comes
+    pub fn is_due (&mut self, now: &Tm) -> bool {
+        if !self.is_pending() {
+            return false;
+        }
+        let today = (now.tm_year, now.tm_yday);
+        let day = self.days.map(|days| days[now.tm_wday as usize]).unwrap_or(true);
+        if day && now.tm_hour == self.hour && now.tm_min == self.minute && self.rang != Some(today) {
+            self.rang = Some(today);
+            true
+        } else {
+            false
+        }
+    }
+
+    // false for an alarm without days once it has rung
+    pub fn is_pending (&self) -> bool {
+        self.days.is_some() || self.rang.is_none()
+    }
+
+    pub fn label (&self) -> String {
+        let time = format!("{:02}:{:02}", self.hour, self.minute);
+        match self.days {
+            None => time,
+            Some([true, true, true, true, true, true, true]) => format!("{} daily", time),
+            Some([false, true, true, true, true, true, false]) => format!("{} weekdays", time),
+            Some([true, false, false, false, false, false, true]) => format!("{} weekends", time),
+            Some(days) => {
+                let names: Vec<&str> = DAY_NAMES.iter().zip(days.iter())
+                    .filter(|&(_, &day)| day).map(|(&name, _)| name).collect();
+                format!("{} {}", time, names.join(","))
+            },
+        }
+    }
+}
+
+fn parse_time (text: &str) -> Option<(i32, i32)> {
+    let lower = text.to_lowercase();
+    let (clock, offset) = if lower.ends_with("am") {
+        (&lower[..lower.len() - 2], Some(0))
+    } else if lower.ends_with("pm") {
+        (&lower[..lower.len() - 2], Some(12))
+    } else {
+        (&lower[..], None)
+    };
+    let mut parts = clock.splitn(2, ':');
+    let hour: i32 = parts.next()?.parse().ok()?;
+    let minute: i32 = match parts.next() {
+        Some(minute) if minute.len() == 2 => minute.parse().ok()?,
+        Some(_) => return None,
+        None if offset.is_some() => 0,
+        None => return None,
+    };
+    let hour = match offset {
+        Some(offset) if (1..13).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    if (0..24).contains(&hour) && (0..60).contains(&minute) { Some((hour, minute)) } else { None }
+}
+
+fn parse_days (text: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    for part in text.to_lowercase().split(',') {
+        match part {
+            "daily" => days = [true; 7],
+            "weekdays" => (1..6).for_each(|day| days[day] = true),
+            "weekends" => {
+                days[0] = true;
+                days[6] = true;
+            },
+            _ => {
+                let mut range = part.splitn(2, '-');
+                let first = day_index(range.next()?)?;
+                let last = match range.next() {
+                    Some(last) => day_index(last)?,
+                    None => first,
+                };
+                // a range may go round the end of the week, as in fri-mon
+                let mut day = first;
+                loop {
+                    days[day] = true;
+                    if day == last {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            },
+        }
+    }
+    Some(days)
+}
+
+// `mon` or `monday`, in lower case
+fn day_index (name: &str) -> Option<usize> {
+    DAY_NAMES.iter().position(|&day| name == day)
+        .or_else(|| FULL_DAY_NAMES.iter().position(|&day| name == day))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use time;
+    use time::Tm;
+
+    use super::*;
+
+    // the given day of 2025, which began on a Wednesday
+    fn at (yday: i32, hour: i32, minute: i32) -> Tm {
+        let mut tm = time::empty_tm();
+        tm.tm_year = 125;
+        tm.tm_yday = yday;
+        tm.tm_wday = (3 + yday) % 7;
+        tm.tm_hour = hour;
+        tm.tm_min = minute;
+        tm
+    }
+
+    #[test]
+    fn times () {
+        assert_eq!(parse_time("7:30"), Some((7, 30)));
+        assert_eq!(parse_time("19:00"), Some((19, 0)));
+        assert_eq!(parse_time("6:45pm"), Some((18, 45)));
+        assert_eq!(parse_time("7PM"), Some((19, 0)));
+        assert_eq!(parse_time("12am"), Some((0, 0)));
+        assert_eq!(parse_time("12:15pm"), Some((12, 15)));
+        for text in ["7", "7:5", "7:300", "24:00", "23:60", "13pm", "0am", "-1:00", "7:30xm", ""].iter() {
+            assert_eq!(parse_time(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn day_names () {
+        assert_eq!(day_index("sun"), Some(0));
+        assert_eq!(day_index("saturday"), Some(6));
+        for name in ["monkey", "mo", "sundays", "tues", ""].iter() {
+            assert_eq!(day_index(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn days () {
+        let (t, f) = (true, false);
+        assert_eq!(parse_days("mon-fri"), Some([f, t, t, t, t, t, f]));
+        assert_eq!(parse_days("weekdays"), Some([f, t, t, t, t, t, f]));
+        assert_eq!(parse_days("Sat,Sun"), Some([t, f, f, f, f, f, t]));
+        assert_eq!(parse_days("weekends"), Some([t, f, f, f, f, f, t]));
+        assert_eq!(parse_days("daily"), Some([t; 7]));
+        assert_eq!(parse_days("fri-mon"), Some([t, t, f, f, f, t, t]));
+        assert_eq!(parse_days("tuesday,thu"), Some([f, f, t, f, t, f, f]));
+        for text in ["monkey", "mon-", "mon-funday", "mon,,fri", "sometimes"].iter() {
+            assert_eq!(parse_days(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn alarms () {
+        assert_eq!(Alarm::parse("7:30 mon-fri").unwrap().label(), "07:30 weekdays");
+        assert_eq!(Alarm::parse("6:45pm").unwrap().label(), "18:45");
+        assert_eq!(Alarm::parse("10:00 sun,tue").unwrap().label(), "10:00 sun,tue");
+        assert!(Alarm::parse("7:30 mon-fri now").is_err());
+        assert!(Alarm::parse("7:30 monkey").is_err());
+        assert!(Alarm::parse("").is_err());
+    }
+
+    #[test]
+    fn alarms_without_days_ring_once () {
+        let mut alarm = Alarm::parse("7:30").unwrap();
+        assert!(!alarm.is_due(&at(0, 7, 29)));
+        assert!(alarm.is_due(&at(0, 7, 30)));
+        assert!(!alarm.is_due(&at(0, 7, 30)));
+        assert!(!alarm.is_pending());
+        assert!(!alarm.is_due(&at(1, 7, 30)));
+    }
+
+    #[test]
+    fn alarms_with_days_ring_once_on_each_of_them () {
+        // from Wednesday to Sunday
+        let mut alarm = Alarm::parse("7:30 weekdays").unwrap();
+        let rings: Vec<bool> = (0..5).map(|day| {
+            let due = alarm.is_due(&at(day, 7, 30));
+            assert!(!alarm.is_due(&at(day, 7, 30)));
+            due
+        }).collect();
+        assert_eq!(rings, vec![true, true, true, false, false]);
+        assert!(alarm.is_pending());
+    }
+}
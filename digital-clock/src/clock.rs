@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use termion::event::Key;
+use time::Tm;
+
+use display::Display;
+use options::Options;
+use Screen;
+
+
+pub struct Clock {
+    options: Options,
+    // the index of the alarm ringing and since when
+    ringing: Option<(usize, Instant)>,
+    // alarms which came due while another one was ringing, in turn
+    waiting: VecDeque<usize>,
+    bells: u64,
+    // snoozed alarms ring again at the given times
+    snoozed: Vec<(usize, Instant)>,
+}
+
+impl Clock {
+    pub fn new (options: Options) -> Clock {
+        Clock { options, ringing: None, waiting: VecDeque::new(), bells: 0, snoozed: Vec::new() }
+    }
+
+    // `now` is the time of day shown and `instant` the same moment for the snoozes
+    fn check_alarms (&mut self, now: &Tm, instant: Instant) {
+        for (i, alarm) in self.options.alarms.iter_mut().enumerate() {
+            if alarm.is_due(now) {
+                self.waiting.push_back(i);
+            }
+        }
+        let (over, snoozed): (Vec<_>, Vec<_>) = self.snoozed.drain(..).partition(|&(_, until)| instant >= until);
+        self.snoozed = snoozed;
+        self.waiting.extend(over.into_iter().map(|(i, _)| i));
+        if self.ringing.is_none() {
+            if let Some(i) = self.waiting.pop_front() {
+                self.ringing = Some((i, instant));
+                self.bells = 0;
+            }
+        }
+    }
+
+    fn snooze (&mut self, instant: Instant) {
+        if let Some((i, _)) = self.ringing.take() {
+            self.snoozed.push((i, instant + self.options.snooze));
+        }
+    }
+
+    fn alarm_line (&self, now: &Tm) -> String {
+        if let Some((i, _)) = self.ringing {
+            return format!("alarm {}, space to dismiss, s to snooze", self.options.alarms[i].label());
+        }
+        if let Some(until) = self.snoozed.iter().map(|&(_, until)| until).min() {
+            let left = until.saturating_duration_since(Instant::now()).as_secs() as i32 + 59;
+            let minutes = (now.tm_hour * 60 + now.tm_min + left / 60) % (24 * 60);
+            return format!("snoozed until {:02}:{:02}", minutes / 60, minutes % 60);
+        }
+        let alarms: Vec<String> = self.options.alarms.iter()
+            .filter(|alarm| alarm.is_pending()).map(|alarm| alarm.label()).collect();
+        if alarms.is_empty() { String::new() } else { format!("alarms: {}", alarms.join(", ")) }
+    }
+}
+
+fn show_time (display: &Display, now: &Tm, twelve_hour: bool) {
+    if twelve_hour {
+        display.show_12_hour(now.tm_hour, now.tm_min, now.tm_sec);
+    } else {
+        display.show_time(now.tm_hour, now.tm_min, now.tm_sec);
+    }
+}
+
+impl Screen for Clock {
+    fn key (&mut self, key: Key, _: &Display) {
+        match (key, self.ringing) {
+            (Key::Char(' '), Some(_)) => self.ringing = None,
+            (Key::Char('s'), Some(_)) => self.snooze(Instant::now()),
+            _ => {},
+        }
+    }
+
+    fn draw (&mut self, display: &Display) {
+        let now = self.options.zone.now();
+        self.check_alarms(&now, Instant::now());
+        let twelve_hour = self.options.twelve_hour;
+        let show = || show_time(display, &now, twelve_hour);
+        match self.ringing {
+            Some((_, since)) => display.ring(since, &mut self.bells, show),
+            None => show(),
+        }
+        let mut line = 0;
+        if self.options.date {
+            let mut date = format!("{} {} {} {}", now.strftime("%A").unwrap(), now.tm_mday,
+                                   now.strftime("%B").unwrap(), now.tm_year + 1900);
+            if let Some(zone) = self.options.zone.label() {
+                date = format!("{}, {}", date, zone);
+            }
+            display.show_line(line, &date);
+            line += 1;
+        }
+        if !self.options.alarms.is_empty() {
+            display.show_line(line, &self.alarm_line(&now));
+        }
+    }
+
+    // the date and the alarms
+    fn lines (&self) -> u16 {
+        self.options.date as u16 + !self.options.alarms.is_empty() as u16
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use time;
+    use time::Tm;
+
+    use options::Options;
+    use super::*;
+
+    fn clock (args: &[&str]) -> Clock {
+        Clock::new(Options::from_args(args.iter().map(|arg| arg.to_string())).unwrap())
+    }
+
+    fn at (hour: i32, minute: i32) -> Tm {
+        let mut tm = time::empty_tm();
+        tm.tm_hour = hour;
+        tm.tm_min = minute;
+        tm
+    }
+
+    fn ringing (clock: &Clock) -> Option<usize> {
+        clock.ringing.map(|(i, _)| i)
+    }
+
+    #[test]
+    fn alarms_due_while_another_rings_wait_their_turn () {
+        let mut clock = clock(&["--alarm", "7:30", "--alarm", "7:31", "--alarm", "7:31 daily"]);
+        let start = Instant::now();
+        clock.check_alarms(&at(7, 30), start);
+        assert_eq!(ringing(&clock), Some(0));
+        clock.check_alarms(&at(7, 31), start);
+        assert_eq!(ringing(&clock), Some(0));
+
+        clock.ringing = None;
+        clock.check_alarms(&at(7, 32), start);
+        assert_eq!(ringing(&clock), Some(1));
+        clock.ringing = None;
+        clock.check_alarms(&at(7, 32), start);
+        assert_eq!(ringing(&clock), Some(2));
+        clock.ringing = None;
+        clock.check_alarms(&at(7, 32), start);
+        assert_eq!(ringing(&clock), None);
+    }
+
+    #[test]
+    fn snoozed_alarms_ring_again_even_after_another_one () {
+        let mut clock = clock(&["--alarm", "7:30", "--alarm", "7:35", "--snooze", "5"]);
+        let start = Instant::now();
+        let minutes = |n: u64| start + Duration::from_secs(n * 60);
+        clock.check_alarms(&at(7, 30), start);
+        clock.snooze(start);
+        assert_eq!(ringing(&clock), None);
+        assert_eq!(clock.alarm_line(&at(7, 30)), "snoozed until 07:35");
+        clock.check_alarms(&at(7, 34), minutes(4));
+        assert_eq!(ringing(&clock), None);
+
+        // both come due at once, and the second alarm is snoozed as well
+        clock.check_alarms(&at(7, 35), minutes(5));
+        assert_eq!(ringing(&clock), Some(1));
+        clock.snooze(minutes(5));
+        clock.check_alarms(&at(7, 35), minutes(5));
+        assert_eq!(ringing(&clock), Some(0));
+        clock.ringing = None;
+        clock.check_alarms(&at(7, 40), minutes(10));
+        assert_eq!(ringing(&clock), Some(1));
+        clock.ringing = None;
+        clock.check_alarms(&at(7, 45), minutes(15));
+        assert_eq!(ringing(&clock), None);
+        assert_eq!(clock.alarm_line(&at(7, 45)), "");
+    }
+}
@@ -0,0 +1,321 @@
+use std::io;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+use termion;
+use termion::cursor::Goto;
+use termion::clear;
+
+use font::{Font, Glyph};
+
+
+const BORDER: char = '*';
+// blank columns and lines between the frame and the digits
+const PADDING: (u16, u16) = (1, 1);
+// blank columns between digits and around the colons, for a font at its own size
+const SPACING: u16 = 1;
+// columns and lines assumed when the terminal does not say
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+// where everything goes for a font at one size, the digits in three pairs
+// separated by colons inside a frame
+struct Layout {
+    font: Font,
+    // the top left corner of the frame
+    origin: (u16, u16),
+    digits: [(u16, u16); 6],
+    colons: [(u16, u16); 2],
+    // AM and PM in the font, or as plain text in the padding under the seconds
+    meridiem: Option<(Glyph, Glyph)>,
+    meridiem_position: (u16, u16),
+    // columns and lines of the frame, including its border
+    width: u16,
+    height: u16,
+}
+
+impl Layout {
+    fn new (font: Font, twelve_hour: bool, spacing: u16, origin: (u16, u16)) -> Layout {
+        let meridiem = match (font.word("AM"), font.word("PM")) {
+            (Some(am), Some(pm)) if twelve_hour => Some((am, pm)),
+            _ => None,
+        };
+        let (x0, y0) = origin;
+        let y = y0 + 1 + PADDING.1;
+        let mut x = x0 + 1 + PADDING.0;
+        let mut digits = [(0, 0); 6];
+        let mut colons = [(0, 0); 2];
+        for (i, digit) in digits.iter_mut().enumerate() {
+            if i == 2 || i == 4 {
+                colons[i / 2 - 1] = (x, y);
+                x += font.colon().width + spacing;
+            }
+            *digit = (x, y);
+            x += font.width + spacing;
+        }
+        let meridiem_position = match meridiem {
+            // apart from the seconds even when the digits are not
+            Some(_) => (x + 1 - spacing.min(1), y),
+            // right under the last digit
+            None => (x - spacing - 2, y + font.height),
+        };
+        if let Some((ref am, ref pm)) = meridiem {
+            x = meridiem_position.0 + am.width.max(pm.width) + spacing;
+        }
+        let width = x - spacing + PADDING.0 + 1 - x0;
+        let height = font.height + 2 * PADDING.1 + 2;
+        Layout { font, origin, digits, colons, meridiem, meridiem_position, width, height }
+    }
+}
+
+// the layout which fits the terminal, centred in it, with the lines of text
+// the screen shows below the frame
+pub struct Display {
+    font: Font,
+    twelve_hour: bool,
+    lines: u16,
+    // lines of the terminal
+    rows: u16,
+    layout: Layout,
+}
+
+impl Display {
+    pub fn new (font: Font, twelve_hour: bool, lines: u16) -> Display {
+        let (layout, rows) = fit(&font, twelve_hour, lines);
+        Display { font, twelve_hour, lines, rows, layout }
+    }
+
+    // lays everything out again for the size of the terminal, once it has changed
+    pub fn resize (&mut self) {
+        let (layout, rows) = fit(&self.font, self.twelve_hour, self.lines);
+        self.layout = layout;
+        self.rows = rows;
+    }
+
+    // line by line, as raw mode moves down without going back to the first column
+    pub fn frame (&self) {
+        println!("{}{}", clear::All, termion::cursor::Hide);
+        let layout = &self.layout;
+        let (x, y) = layout.origin;
+        let edge: String = (0..layout.width).map(|_| BORDER).collect();
+        let side = format!("{}{:2$}{0}", BORDER, "", layout.width as usize - 2);
+        for i in 0..layout.height {
+            let line = if i == 0 || i == layout.height - 1 { &edge } else { &side };
+            println!("{}{}", Goto(x, y + i), line);
+        }
+        for &position in layout.colons.iter() {
+            render(position, layout.font.colon());
+        }
+    }
+
+    // three pairs of digits, hours, minutes and seconds on the clock
+    pub fn show_time (&self, hour: i32, minute: i32, second: i32) {
+        self.show_digits([Some(hour / 10), Some(hour % 10), Some(minute / 10), Some(minute % 10),
+                          Some(second / 10), Some(second % 10)]);
+    }
+
+    // blanks where there is no digit, drawn over whatever was there so nothing flickers
+    fn show_digits (&self, digits: [Option<i32>; 6]) {
+        let layout = &self.layout;
+        for (&position, &digit) in layout.digits.iter().zip(digits.iter()) {
+            match digit {
+                Some(digit) => render(position, layout.font.digit(digit)),
+                None => clear(position, layout.font.width, layout.font.height),
+            }
+        }
+    }
+
+    pub fn show_12_hour (&self, hour: i32, minute: i32, second: i32) {
+        let am = hour < 12;
+        let layout = &self.layout;
+        match layout.meridiem {
+            Some((ref am_glyph, ref pm_glyph)) => {
+                clear(layout.meridiem_position, am_glyph.width.max(pm_glyph.width), layout.font.height);
+                render(layout.meridiem_position, if am { am_glyph } else { pm_glyph });
+            },
+            None => {
+                let (x, y) = layout.meridiem_position;
+                println!("{}{}", Goto(x, y), if am { "AM" } else { "PM" });
+            },
+        }
+        // no leading zero, as in 9:05:00 PM
+        let hour = (hour + 11) % 12 + 1;
+        let tens = if hour < 10 { None } else { Some(hour / 10) };
+        self.show_digits([tens, Some(hour % 10), Some(minute / 10), Some(minute % 10),
+                          Some(second / 10), Some(second % 10)]);
+    }
+
+    // minutes, seconds and hundredths, or hours, minutes and seconds from an hour on
+    pub fn show_duration (&self, duration: Duration) {
+        let hundredths = duration.subsec_nanos() as i32 / 10_000_000;
+        let seconds = duration.as_secs() as i32;
+        if seconds < 60 * 60 {
+            self.show_time(seconds / 60, seconds % 60, hundredths);
+        } else {
+            self.show_time(seconds / 3600 % 100, seconds / 60 % 60, seconds % 60);
+        }
+    }
+
+    // flashes what `draw` shows and rings the bell once a second since `since`,
+    // `bells` counts those already rung
+    pub fn ring<F: FnOnce()> (&self, since: Instant, bells: &mut u64, draw: F) {
+        let elapsed = since.elapsed();
+        if elapsed.subsec_millis() < 500 {
+            draw();
+        } else {
+            let layout = &self.layout;
+            for &position in layout.digits.iter() {
+                clear(position, layout.font.width, layout.font.height);
+            }
+        }
+        if *bells <= elapsed.as_secs() {
+            *bells = elapsed.as_secs() + 1;
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+    }
+
+    // the `number`th line of text under the frame, centred and replacing whatever was there,
+    // unless the terminal is too small for it
+    pub fn show_line (&self, number: u16, text: &str) {
+        let layout = &self.layout;
+        let (x0, y0) = layout.origin;
+        let y = y0 + layout.height + number;
+        if y > self.rows {
+            return;
+        }
+        let x = x0 + 1 + (layout.width - 2).saturating_sub(text.chars().count() as u16) / 2;
+        println!("{}{}{}{}", Goto(1, y), clear::CurrentLine, Goto(x, y), text);
+    }
+}
+
+// the layout for the terminal as it is now, with the terminal's lines
+fn fit (font: &Font, twelve_hour: bool, lines: u16) -> (Layout, u16) {
+    let size = match termion::terminal_size() {
+        Ok((columns, rows)) if columns > 0 && rows > 0 => (columns, rows),
+        _ => DEFAULT_SIZE,
+    };
+    (fit_in(font, twelve_hour, lines, size), size.1)
+}
+
+// the font as large as the frame and the lines below it fit in `columns` and `rows`,
+// or the plain font when even its own size does not fit
+fn fit_in (font: &Font, twelve_hour: bool, lines: u16, (columns, rows): (u16, u16)) -> Layout {
+    let fits = |layout: &Layout| layout.width <= columns && layout.height + lines <= rows;
+    let mut layout = Layout::new(font.scaled(1), twelve_hour, SPACING, (1, 1));
+    let mut spacing = SPACING;
+    if fits(&layout) {
+        for scale in 2.. {
+            let larger = Layout::new(font.scaled(scale), twelve_hour, SPACING * scale, (1, 1));
+            if !fits(&larger) {
+                break;
+            }
+            layout = larger;
+            spacing = SPACING * scale;
+        }
+    } else {
+        layout = Layout::new(Font::plain(), twelve_hour, 0, (1, 1));
+        spacing = 0;
+    }
+    // laid out again in the middle, now that its size is known
+    let origin = (columns.saturating_sub(layout.width) / 2 + 1,
+                  rows.saturating_sub(layout.height + lines) / 2 + 1);
+    Layout::new(layout.font, twelve_hour, spacing, origin)
+}
+
+fn render (position: (u16, u16), glyph: &Glyph) {
+    let (x, y) = position;
+    for (i, line) in glyph.lines.iter().enumerate() {
+        println!("{}{}", Goto(x, y + i as u16), line);
+    }
+}
+
+fn clear (position: (u16, u16), width: u16, height: u16) {
+    let (x, y) = position;
+    for i in 0..height {
+        println!("{}{:2$}", Goto(x, y + i), "", width as usize);
+    }
+}
+
+// leaves the terminal as it was found
+pub fn restore () {
+    println!("{}{}{}", clear::All, Goto(1, 1), termion::cursor::Show);
+}
+
+pub fn format_duration (duration: Duration) -> String {
+    let hundredths = duration.subsec_nanos() / 10_000_000;
+    let seconds = duration.as_secs();
+    if seconds < 60 * 60 {
+        format!("{:02}:{:02}.{:02}", seconds / 60, seconds % 60, hundredths)
+    } else {
+        format!("{}:{:02}:{:02}.{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60, hundredths)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how many times its own size the font is drawn, 0 for the plain font instead
+    fn scale (name: &str, twelve_hour: bool, lines: u16, size: (u16, u16)) -> u16 {
+        let font = Font::load(name).unwrap();
+        let layout = fit_in(&font, twelve_hour, lines, size);
+        if layout.font.height < font.height {
+            return 0;
+        }
+        assert_eq!(layout.font.height % font.height, 0);
+        layout.font.height / font.height
+    }
+
+    #[test]
+    fn the_largest_font_which_fits_is_chosen () {
+        assert_eq!(scale("ascii", false, 1, (80, 24)), 2);
+        assert_eq!(scale("ascii", false, 1, (120, 40)), 4);
+        assert_eq!(scale("ascii", false, 1, (200, 60)), 7);
+        assert_eq!(scale("ascii", false, 1, (40, 10)), 1);
+        // one more line of text under the frame leaves room for the plain font only
+        assert_eq!(scale("ascii", false, 2, (40, 10)), 0);
+        assert_eq!(scale("seven-segment", false, 1, (120, 40)), 2);
+        // AM and PM in the font take room too
+        assert_eq!(scale("seven-segment", true, 1, (120, 40)), 1);
+        assert_eq!(scale("block", true, 1, (200, 60)), 3);
+        assert_eq!(scale("block", false, 1, (200, 60)), 4);
+    }
+
+    #[test]
+    fn the_frame_is_centred () {
+        let layout = fit_in(&Font::load("ascii").unwrap(), false, 1, (80, 24));
+        assert_eq!((layout.width, layout.height), (58, 14));
+        assert_eq!(layout.origin, (12, 5));
+        assert_eq!(layout.digits[0], (14, 7));
+    }
+
+    #[test]
+    fn terminals_too_small_for_the_font_get_the_plain_one () {
+        for &size in [(40, 10), (20, 5), (1, 1), (0, 0)].iter() {
+            let layout = fit_in(&Font::load("seven-segment").unwrap(), true, 2, size);
+            assert_eq!((layout.font.width, layout.font.height), (1, 1), "{:?}", size);
+            assert_eq!((layout.width, layout.height), (15, 5));
+        }
+        assert_eq!(fit_in(&Font::load("seven-segment").unwrap(), true, 2, (40, 10)).origin, (13, 2));
+        // nothing can be centred in a terminal smaller than the frame
+        assert_eq!(fit_in(&Font::load("seven-segment").unwrap(), true, 2, (10, 3)).origin, (1, 1));
+    }
+
+    #[test]
+    fn every_size_gets_a_layout_which_fits_or_the_plain_font () {
+        let plain = Font::plain();
+        for name in ["ascii", "seven-segment", "block", "plain"].iter() {
+            let font = Font::load(name).unwrap();
+            for columns in (0..240).step_by(7) {
+                for rows in (0..80).step_by(3) {
+                    let layout = fit_in(&font, true, 2, (columns, rows));
+                    let fits = layout.width <= columns && layout.height + 2 <= rows;
+                    assert!(fits || layout.font.height == plain.height, "{} {}x{}", name, columns, rows);
+                    let right = layout.origin.0 + layout.width;
+                    assert!(layout.digits.iter().all(|&(x, _)| x + layout.font.width < right));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+
+// the fonts built into the binary besides the default
+const BUNDLED: [(&str, &str); 3] = [
+    ("seven-segment", include_str!("../assets/fonts/seven-segment.txt")),
+    ("block", include_str!("../assets/fonts/block.txt")),
+    (PLAIN_FONT, include_str!("../assets/fonts/plain.txt")),
+];
+
+// the ascii font is kept as a directory, the format fonts on disk may use too
+const ASCII: [(&str, &str); 11] = [
+    ("digit-0.txt", include_str!("../assets/fonts/ascii/digit-0.txt")),
+    ("digit-1.txt", include_str!("../assets/fonts/ascii/digit-1.txt")),
+    ("digit-2.txt", include_str!("../assets/fonts/ascii/digit-2.txt")),
+    ("digit-3.txt", include_str!("../assets/fonts/ascii/digit-3.txt")),
+    ("digit-4.txt", include_str!("../assets/fonts/ascii/digit-4.txt")),
+    ("digit-5.txt", include_str!("../assets/fonts/ascii/digit-5.txt")),
+    ("digit-6.txt", include_str!("../assets/fonts/ascii/digit-6.txt")),
+    ("digit-7.txt", include_str!("../assets/fonts/ascii/digit-7.txt")),
+    ("digit-8.txt", include_str!("../assets/fonts/ascii/digit-8.txt")),
+    ("digit-9.txt", include_str!("../assets/fonts/ascii/digit-9.txt")),
+    ("colon.txt", include_str!("../assets/fonts/ascii/colon.txt")),
+];
+
+pub const DEFAULT_FONT: &str = "ascii";
+// one character per glyph, for terminals too small for any other
+const PLAIN_FONT: &str = "plain";
+
+// a picture made of lines which are all padded to the same width
+pub struct Glyph {
+    pub lines: Vec<String>,
+    pub width: u16,
+}
+
+impl Glyph {
+    fn new (lines: Vec<String>, height: usize) -> Glyph {
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let mut lines: Vec<String> = lines.iter()
+            .map(|line| format!("{:1$}", line, width))
+            .collect();
+        // trailing blank lines may be left out
+        lines.resize(height, " ".repeat(width));
+        Glyph { lines, width: width as u16 }
+    }
+
+    // glyphs side by side with a column between them
+    fn join (glyphs: &[&Glyph]) -> Glyph {
+        let height = glyphs.iter().map(|glyph| glyph.lines.len()).max().unwrap_or(0);
+        let lines = (0..height)
+            .map(|i| glyphs.iter().map(|glyph| glyph.lines[i].as_str()).collect::<Vec<&str>>().join(" "))
+            .collect();
+        Glyph::new(lines, height)
+    }
+
+    // `scale` times as wide and as tall, repeating every character and line
+    fn scaled (&self, scale: u16) -> Glyph {
+        let scale = scale as usize;
+        let mut lines = Vec::new();
+        for line in self.lines.iter() {
+            let wide: String = line.chars().flat_map(|c| iter::repeat_n(c, scale)).collect();
+            lines.extend(iter::repeat_n(wide, scale));
+        }
+        Glyph { lines, width: self.width * scale as u16 }
+    }
+}
+
+// the digits 0 to 9 and a colon, all as tall as each other and the digits as wide,
+// optionally with AM and PM, or letters to spell them
+pub struct Font {
+    glyphs: HashMap<String, Glyph>,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Font {
+    // a bundled font by name, or a font file or directory
+    pub fn load (name: &str) -> Result<Font, String> {
+        if name == DEFAULT_FONT {
+            let files = ASCII.iter().map(|&(file, text)| (file.to_string(), text.to_string())).collect();
+            return Font::from_files(files).map_err(|e| format!("font {}: {}", name, e));
+        }
+        if let Some(&(_, text)) = BUNDLED.iter().find(|font| font.0 == name) {
+            return Font::parse(text).map_err(|e| format!("font {}: {}", name, e));
+        }
+        let path = Path::new(name);
+        let font = if path.is_dir() {
+            let mut files = Vec::new();
+            let entries = fs::read_dir(path).map_err(|e| format!("cannot read font {}: {}", name, e))?;
+            for entry in entries {
+                let path = entry.map_err(|e| format!("cannot read font {}: {}", name, e))?.path();
+                let file = path.file_name().and_then(|file| file.to_str()).unwrap_or("").to_string();
+                if file.ends_with(".txt") {
+                    files.push((file, read(&path)?));
+                }
+            }
+            Font::from_files(files)
+        } else if path.is_file() {
+            Font::parse(&read(path)?)
+        } else {
+            let mut names = vec![DEFAULT_FONT];
+            names.extend(BUNDLED.iter().map(|font| font.0));
+            return Err(format!("no font called {}, there are {} or a font file or directory",
+                               name, names.join(", ")));
+        };
+        font.map_err(|e| format!("font {}: {}", name, e))
+    }
+
+    // a directory of `digit-0.txt` to `digit-9.txt`, `colon.txt` and optionally
+    // `am.txt`, `pm.txt` and `letter-a.txt` to `letter-z.txt`
+    fn from_files (files: Vec<(String, String)>) -> Result<Font, String> {
+        let mut glyphs = Vec::new();
+        for (file, text) in files {
+            let stem = file.trim_end_matches(".txt");
+            let key = match stem {
+                "colon" => ":".to_string(),
+                "am" | "pm" => stem.to_uppercase(),
+                _ if stem.starts_with("digit-") => stem[6..].to_string(),
+                _ if stem.starts_with("letter-") => stem[7..].to_uppercase(),
+                _ => return Err(format!("unexpected file {}", file)),
+            };
+            glyphs.push((key, text.lines().map(str::to_string).collect()));
+        }
+        Font::new(glyphs)
+    }
+
+    // a single file of glyphs, each after its name in brackets such as `[7]`, `[:]`,
+    // `[AM]` or `[Q]`, with comments starting with # before the first one
+    fn parse (text: &str) -> Result<Font, String> {
+        let mut glyphs: Vec<(String, Vec<String>)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.starts_with('[') && line.ends_with(']') && line.len() > 2 {
+                glyphs.push((line[1..line.len() - 1].to_string(), Vec::new()));
+            } else if let Some(glyph) = glyphs.last_mut() {
+                glyph.1.push(line.to_string());
+            } else if !line.trim().is_empty() && !line.starts_with('#') {
+                return Err(format!("line {}: expected a glyph name such as [0]", number + 1));
+            }
+        }
+        Font::new(glyphs)
+    }
+
+    fn new (mut glyphs: Vec<(String, Vec<String>)>) -> Result<Font, String> {
+        for glyph in glyphs.iter_mut() {
+            while glyph.1.last().map(|line| line.trim().is_empty()).unwrap_or(false) {
+                glyph.1.pop();
+            }
+        }
+        let digits: Vec<String> = (0..10).map(|digit| digit.to_string()).collect();
+        let mut keys = digits.clone();
+        keys.extend([":", "AM", "PM"].iter().map(|key| key.to_string()));
+        keys.extend((b'A'..=b'Z').map(|letter| (letter as char).to_string()));
+        if let Some(glyph) = glyphs.iter().find(|glyph| !keys.contains(&glyph.0)) {
+            return Err(format!("unexpected glyph {}", glyph.0));
+        }
+        for (i, glyph) in glyphs.iter().enumerate() {
+            if glyphs[..i].iter().any(|other| other.0 == glyph.0) {
+                return Err(format!("glyph {} is given twice", glyph.0));
+            }
+        }
+        for key in digits.iter().chain(Some(&":".to_string())) {
+            if !glyphs.iter().any(|glyph| &glyph.0 == key) {
+                return Err(format!("glyph {} is missing", key));
+            }
+        }
+
+        let height = glyphs.iter()
+            .filter(|glyph| digits.contains(&glyph.0))
+            .map(|glyph| glyph.1.len())
+            .max().unwrap();
+        let glyphs: HashMap<String, Glyph> = glyphs.into_iter()
+            .map(|(key, lines)| {
+                if lines.len() > height {
+                    Err(format!("glyph {} is {} lines tall, the digits are {}",
+                                key, lines.len(), height))
+                } else {
+                    Ok((key, Glyph::new(lines, height)))
+                }
+            })
+            .collect::<Result<_, String>>()?;
+        let width = glyphs["0"].width;
+        for digit in digits.iter() {
+            if glyphs[digit].width != width {
+                return Err(format!("glyph {} is {} columns wide, the other digits are {}",
+                                   digit, glyphs[digit].width, width));
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err("the digits are empty".to_string());
+        }
+        Ok(Font { glyphs, width, height: height as u16 })
+    }
+
+    pub fn plain () -> Font {
+        Font::load(PLAIN_FONT).unwrap()
+    }
+
+    pub fn scaled (&self, scale: u16) -> Font {
+        Font {
+            glyphs: self.glyphs.iter().map(|(key, glyph)| (key.clone(), glyph.scaled(scale))).collect(),
+            width: self.width * scale,
+            height: self.height * scale,
+        }
+    }
+
+    pub fn digit (&self, digit: i32) -> &Glyph {
+        &self.glyphs[&digit.to_string()]
+    }
+
+    pub fn colon (&self) -> &Glyph {
+        &self.glyphs[":"]
+    }
+
+    // AM or PM as a glyph of its own, or spelt in letters
+    pub fn word (&self, word: &str) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(word) {
+            return Some(Glyph::new(glyph.lines.clone(), self.height as usize));
+        }
+        let letters: Option<Vec<&Glyph>> = word.chars()
+            .map(|letter| self.glyphs.get(&letter.to_string()))
+            .collect();
+        letters.map(|letters| Glyph::join(&letters))
+    }
+}
+
+fn read (path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    // every digit as two lines of itself, a one line colon and `extra`
+    fn font_text (extra: &str) -> String {
+        let digits: String = (0..10).map(|digit| format!("[{0}]\n{0}{0}\n{0}{0}\n", digit)).collect();
+        format!("# a test font\n\n{}[:]\n.\n{}", digits, extra)
+    }
+
+    #[test]
+    fn bundled_fonts_load () {
+        for name in iter::once(DEFAULT_FONT).chain(BUNDLED.iter().map(|font| font.0)) {
+            let font = Font::load(name).unwrap();
+            assert!(font.width > 0 && font.height > 0, "{}", name);
+            for digit in 0..10 {
+                assert_eq!(font.digit(digit).width, font.width, "{} {}", name, digit);
+                assert_eq!(font.digit(digit).lines.len(), font.height as usize, "{} {}", name, digit);
+            }
+            assert_eq!(font.colon().lines.len(), font.height as usize, "{}", name);
+        }
+        let plain = Font::plain();
+        assert_eq!((plain.width, plain.height), (1, 1));
+        assert_eq!(plain.digit(7).lines, vec!["7"]);
+        assert!(Font::load("nothing-called-this").err().unwrap().starts_with("no font called nothing-called-this"));
+    }
+
+    #[test]
+    fn single_file_fonts () {
+        let font = Font::parse(&font_text("[AM]\nam\n\n\n[P]\np\n[M]\nm\n")).unwrap();
+        assert_eq!((font.width, font.height), (2, 2));
+        assert_eq!(font.digit(3).lines, vec!["33", "33"]);
+        // short glyphs are padded to the height of the digits
+        assert_eq!(font.colon().lines, vec![".", " "]);
+        assert_eq!(font.word("AM").unwrap().lines, vec!["am", "  "]);
+        assert_eq!(font.word("PM").unwrap().lines, vec!["p m", "   "]);
+        assert!(font.word("XM").is_none());
+    }
+
+    #[test]
+    fn directory_fonts () {
+        let dir = env::temp_dir().join(format!("digital-clock-font-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for digit in 0..10 {
+            fs::write(dir.join(format!("digit-{}.txt", digit)), format!(" {}\n{} \n", digit, digit)).unwrap();
+        }
+        fs::write(dir.join("colon.txt"), ":\n").unwrap();
+        fs::write(dir.join("letter-a.txt"), "a\n").unwrap();
+        fs::write(dir.join("letter-m.txt"), "m\n").unwrap();
+        fs::write(dir.join("README"), "not a glyph\n").unwrap();
+        let font = Font::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!((font.width, font.height), (2, 2));
+        assert_eq!(font.digit(5).lines, vec![" 5", "5 "]);
+        assert_eq!(font.word("AM").unwrap().lines, vec!["a m", "   "]);
+        assert!(font.word("PM").is_none());
+
+        fs::write(dir.join("digit-10.txt"), "10\n").unwrap();
+        assert_eq!(Font::load(dir.to_str().unwrap()).err().unwrap(),
+                   format!("font {}: unexpected glyph 10", dir.display()));
+        fs::write(dir.join("dot.txt"), ".\n").unwrap();
+        assert!(Font::load(dir.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_fonts_are_refused () {
+        let cases = [
+            ("", "glyph 0 is missing"),
+            ("stray\n[0]\n0\n", "line 1: expected a glyph name such as [0]"),
+            (&font_text("")[..font_text("").find("[7]").unwrap()], "glyph 7 is missing"),
+            (&font_text("[10]\n10\n"), "unexpected glyph 10"),
+            (&font_text("[a]\na\n"), "unexpected glyph a"),
+            (&font_text("[3]\n3\n"), "glyph 3 is given twice"),
+            (&font_text("[AM]\na\nm\n!\n"), "glyph AM is 3 lines tall, the digits are 2"),
+            (&font_text("").replace("[4]\n44\n", "[4]\n444\n"), "glyph 4 is 3 columns wide, the other digits are 2"),
+        ];
+        for &(text, error) in cases.iter() {
+            assert_eq!(Font::parse(text).err(), Some(error.to_string()), "{:?}", text);
+        }
+        let empty: String = (0..10).map(|digit| format!("[{}]\n \n", digit)).collect();
+        assert_eq!(Font::parse(&format!("{}[:]\n", empty)).err(), Some("the digits are empty".to_string()));
+    }
+
+    #[test]
+    fn every_truncated_font_loads_or_fails_without_panicking () {
+        for &(_, text) in BUNDLED.iter() {
+            for (end, _) in text.char_indices() {
+                let _ = Font::parse(&text[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_fonts () {
+        let font = Font::parse(&font_text("")).unwrap().scaled(2);
+        assert_eq!((font.width, font.height), (4, 4));
+        assert_eq!(font.digit(1).lines, vec!["1111", "1111", "1111", "1111"]);
+        assert_eq!(font.colon().lines, vec!["..", "..", "  ", "  "]);
+    }
+}
@@ -1,45 +1,111 @@
-
+extern crate signal_hook;
 extern crate termion;
 extern crate time;
 
+mod alarm;
+mod clock;
+mod display;
+mod font;
+mod options;
+mod stopwatch;
+mod timer;
+mod zone;
 
-const FRAME: &'static str = include_str!("../assets/frame.txt");
-
-const DIGITS: [&'static str; 10] = [
-    include_str!("../assets/digit-0.txt"),
-    include_str!("../assets/digit-1.txt"),
-    include_str!("../assets/digit-2.txt"),
-    include_str!("../assets/digit-3.txt"),
-    include_str!("../assets/digit-4.txt"),
-    include_str!("../assets/digit-5.txt"),
-    include_str!("../assets/digit-6.txt"),
-    include_str!("../assets/digit-7.txt"),
-    include_str!("../assets/digit-8.txt"),
-    include_str!("../assets/digit-9.txt"),
-];
-
-fn render_digit (position: (u16, u16), digit: i32) {
-    let (x, y) = position;
-    for (i, line) in DIGITS[digit as usize].split('\n').enumerate() {
-        println!("{}{}", termion::cursor::Goto(x, y + i as u16), line);
-    }
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use signal_hook::consts::SIGWINCH;
+use termion::AsyncReader;
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::IntoRawMode;
+
+use clock::Clock;
+use display::Display;
+use font::Font;
+use options::{Mode, Options, USAGE};
+use stopwatch::Stopwatch;
+use timer::Timer;
+
+
+// keys as they are pressed, and whether the terminal has been resized since it was last laid out
+struct Terminal {
+    input: Keys<AsyncReader>,
+    resized: Arc<AtomicBool>,
 }
 
-fn display_time (hour: i32, minute: i32, second: i32) {
-    render_digit((4, 4), hour / 10);
-    render_digit((8, 4), hour % 10);
-    render_digit((14, 4), minute / 10);
-    render_digit((18, 4), minute % 10);
-    render_digit((24, 4), second / 10);
-    render_digit((28, 4), second % 10);
+// what is shown inside the frame: the clock, the stopwatch or the timer
+pub trait Screen {
+    fn key (&mut self, key: Key, display: &Display);
+    // draws what does not change, once the frame is there
+    fn start (&mut self, _: &Display) {}
+    fn draw (&mut self, display: &Display);
+    // how many lines of text it shows under the frame
+    fn lines (&self) -> u16;
 }
 
-fn main() {
-    println!("{}", termion::clear::All);
-    println!("{}{}", termion::cursor::Goto(1, 1), FRAME);
+// redraws the screen every `tick` until q or ctrl-c is pressed, laying it out
+// again whenever the terminal is resized
+fn run<S: Screen> (mut screen: S, font: Font, twelve_hour: bool,
+                   terminal: &mut Terminal, tick: Duration) {
+    let mut display = Display::new(font, twelve_hour, screen.lines());
+    display.frame();
+    screen.start(&display);
     loop {
-        let now = time::now();
-        display_time(now.tm_hour, now.tm_min, now.tm_sec);
-        std::thread::sleep(std::time::Duration::from_millis(250));
+        if terminal.resized.swap(false, Ordering::Relaxed) {
+            display.resize();
+            display.frame();
+            screen.start(&display);
+        }
+        while let Some(Ok(key)) = terminal.input.next() {
+            match key {
+                Key::Char('q') | Key::Ctrl('c') => return,
+                key => screen.key(key, &display),
+            }
+        }
+        screen.draw(&display);
+        thread::sleep(tick);
     }
 }
+
+fn main() {
+    if env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = unwrap_exit(Options::from_args(env::args().skip(1)));
+    options.zone.apply();
+    let font = unwrap_exit(Font::load(&options.font));
+    let twelve_hour = options.twelve_hour;
+    let resized = Arc::new(AtomicBool::new(false));
+    unwrap_exit(signal_hook::flag::register(SIGWINCH, Arc::clone(&resized))
+                .map_err(|e| format!("cannot watch the terminal size: {}", e)));
+
+    // the terminal is restored when this is dropped
+    let raw = unwrap_exit(io::stdout().into_raw_mode()
+                          .map_err(|e| format!("cannot use the terminal: {}", e)));
+    let mut terminal = Terminal { input: termion::async_stdin().keys(), resized };
+    match options.mode {
+        Mode::Clock =>
+            run(Clock::new(options), font, twelve_hour, &mut terminal, Duration::from_millis(250)),
+        Mode::Stopwatch =>
+            run(Stopwatch::new(), font, false, &mut terminal, Duration::from_millis(10)),
+        Mode::Timer(duration) =>
+            run(Timer::new(duration), font, false, &mut terminal, Duration::from_millis(50)),
+    }
+    display::restore();
+    drop(raw);
+}
+
+fn unwrap_exit<T> (result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        let _ = writeln!(io::stderr(), "Error: {}", e);
+        process::exit(1);
+    })
+}
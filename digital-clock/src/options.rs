@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::time::Duration;
+
+use alarm::Alarm;
+use font::DEFAULT_FONT;
+use timer::parse_duration;
+use zone::Zone;
+
+
+pub const USAGE: &str = "Usage: digital-clock [options] [stopwatch | timer <duration>]
+
+Modes:
+    (none)             show the time, q to quit
+    stopwatch          count up in hundredths, space starts and stops, l marks a lap
+                       and r resets
+    timer <duration>   count down from <duration>, e.g. 90, 25m, 1h30m or 10:00, at most
+                       99:59:59, then ring and flash until space is pressed; space pauses
+                       and r restarts
+
+Options:
+    --12-hour          show the hours from 1 to 12 followed by AM or PM
+    --24-hour          show the hours from 0 to 23 (the default)
+    --date             show the date under the clock
+    --timezone <zone>  show the time in <zone>, either a name from the tz database
+                       such as Europe/Paris or an offset from UTC such as +05:30 or UTC-8
+    --alarm <alarm>    ring and flash at a time such as 7:30 or 6:45pm, followed by the
+                       days to ring on, e.g. \"7:30 mon-fri\", \"10:00 sat,sun\" or \"9:00 daily\",
+                       or only once without them (may be given more than once);
+                       space dismisses a ringing alarm and s snoozes it
+    --snooze <minutes> how long s puts off a ringing alarm (default 9)
+    --font <font>      draw the digits with ascii (the default), seven-segment, block or plain,
+                       or a font of your own: a directory of digit-0.txt to digit-9.txt
+                       and colon.txt, optionally with am.txt and pm.txt or letter-a.txt
+                       to letter-z.txt, or a single file with each glyph after its name
+                       in brackets, such as [7], [:], [AM] or [Q]; the digits are made
+                       larger to fill the terminal, or plain when it is too small for them
+    -c, --config <file>
+                       read options from <file>, one `key = value` per line
+    -h, --help         print this message
+
+Every long option can also be written in the config file, e.g. `alarm = 7:30 weekdays`
+or `date = yes`. Options given on the command line take precedence over the config file.";
+
+const DEFAULT_SNOOZE: u64 = 9;
+// the display has two digits for the hours
+const MAX_TIMER: Duration = Duration::from_secs(100 * 60 * 60);
+
+// options which take no value on the command line
+const FLAGS: [&str; 3] = ["12-hour", "24-hour", "date"];
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Clock,
+    Stopwatch,
+    Timer(Duration),
+}
+
+pub struct Options {
+    pub mode: Mode,
+    pub twelve_hour: bool,
+    pub date: bool,
+    pub zone: Zone,
+    pub alarms: Vec<Alarm>,
+    pub snooze: Duration,
+    pub font: String,
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item=String>> (mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            mode: Mode::Clock,
+            twelve_hour: false,
+            date: false,
+            zone: Zone::Local,
+            alarms: Vec::new(),
+            snooze: Duration::from_secs(DEFAULT_SNOOZE * 60),
+            font: DEFAULT_FONT.to_string(),
+        };
+
+        let mut given = Vec::new();
+        while let Some(arg) = args.next() {
+            let key = match arg.as_str() {
+                "stopwatch" => {
+                    options.mode = Mode::Stopwatch;
+                    continue;
+                },
+                "timer" => {
+                    options.mode = Mode::Timer(parse_timer(args.next())?);
+                    continue;
+                },
+                "-c" => "config".to_string(),
+                _ if arg.starts_with("--") => arg[2..].to_string(),
+                _ => return Err(format!("unexpected argument `{}`\n\n{}", arg, USAGE)),
+            };
+            if FLAGS.contains(&key.as_str()) {
+                given.push((key, "yes".to_string()));
+                continue;
+            }
+            match args.next() {
+                Some(value) => given.push((key, value)),
+                None => return Err(format!("missing value for `{}`", arg)),
+            }
+        }
+
+        for (_, path) in given.iter().filter(|option| option.0 == "config") {
+            for (key, value) in read_config_file(path)? {
+                options.apply(&key, &value).map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+        // alarms from the command line replace those from the file
+        if given.iter().any(|option| option.0 == "alarm") {
+            options.alarms.clear();
+        }
+        for (key, value) in given.iter().filter(|option| option.0 != "config") {
+            options.apply(key, value)?;
+        }
+        Ok(options)
+    }
+
+    fn apply (&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "12-hour" => self.twelve_hour = parse_bool(value)?,
+            "24-hour" => self.twelve_hour = !parse_bool(value)?,
+            "date" => self.date = parse_bool(value)?,
+            "timezone" => self.zone = Zone::parse(value)?,
+            "alarm" => self.alarms.push(Alarm::parse(value)?),
+            "font" => self.font = value.to_string(),
+            "snooze" => match value.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => self.snooze = Duration::from_secs(minutes * 60),
+                _ => return Err(format!("expected a number of minutes, found `{}`", value)),
+            },
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_timer (duration: Option<String>) -> Result<Duration, String> {
+    let text = duration.ok_or("missing duration for `timer`".to_string())?;
+    match parse_duration(&text) {
+        Some(duration) if duration.as_secs() > 0 && duration < MAX_TIMER => Ok(duration),
+        _ => Err(format!("invalid duration `{}`", text)),
+    }
+}
+
+fn parse_bool (value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected true or false, found `{}`", value)),
+    }
+}
+
+fn read_config_file (path: &str) -> Result<Vec<(String, String)>, String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("cannot read config file {}: {}", path, e))?;
+    parse_config(path, &content)
+}
+
+fn parse_config (path: &str, content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut options = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.find('=') {
+            Some(i) => options.push((line[..i].trim().to_string(), line[i + 1..].trim().to_string())),
+            None => return Err(format!("{}:{}: expected `key = value`", path, number + 1)),
+        }
+    }
+    Ok(options)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    fn options (args: &[&str]) -> Result<Options, String> {
+        Options::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn config_lines () {
+        let content = "# the clock\n\n  date = yes\nalarm=7:30 mon-fri\n  font =  block  \n";
+        assert_eq!(parse_config("clock.conf", content), Ok(vec![
+            ("date".to_string(), "yes".to_string()),
+            ("alarm".to_string(), "7:30 mon-fri".to_string()),
+            ("font".to_string(), "block".to_string()),
+        ]));
+        assert_eq!(parse_config("clock.conf", "date = yes\n12-hour\n"),
+                   Err("clock.conf:2: expected `key = value`".to_string()));
+    }
+
+    #[test]
+    fn the_command_line_takes_precedence_over_the_config_file () {
+        let path = env::temp_dir().join(format!("digital-clock-{}.conf", process::id()));
+        fs::write(&path, "date = yes\nsnooze = 5\nalarm = 7:30\nalarm = 8:00 weekends\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let from_file = options(&["-c", path]).unwrap();
+        assert!(from_file.date);
+        assert_eq!(from_file.snooze, Duration::from_secs(5 * 60));
+        assert_eq!(from_file.alarms.iter().map(Alarm::label).collect::<Vec<_>>(), vec!["07:30", "08:00 weekends"]);
+
+        let given = options(&["--config", path, "--alarm", "6:00", "--snooze", "1"]).unwrap();
+        assert!(given.date);
+        assert_eq!(given.snooze, Duration::from_secs(60));
+        assert_eq!(given.alarms.iter().map(Alarm::label).collect::<Vec<_>>(), vec!["06:00"]);
+
+        fs::write(path, "snooze = never\n").unwrap();
+        assert_eq!(options(&["-c", path]).err().unwrap(),
+                   format!("{}: expected a number of minutes, found `never`", path));
+        fs::remove_file(path).unwrap();
+        assert!(options(&["-c", path]).err().unwrap().starts_with("cannot read config file"));
+    }
+
+    #[test]
+    fn arguments () {
+        assert!(options(&["--alarm", "7:30 monkey"]).is_err());
+        assert!(options(&["--date", "--alarm"]).is_err());
+        assert!(options(&["--bogus", "1"]).is_err());
+        assert!(options(&["timer"]).is_err());
+        assert!(options(&["timer", "0"]).is_err());
+        assert!(options(&["timer", "100h"]).is_err());
+        assert!(options(&["timer", "99:59:59"]).is_ok());
+        assert!(options(&["timer", "25m"]).unwrap().mode == Mode::Timer(Duration::from_secs(25 * 60)));
+        assert!(options(&["--12-hour"]).unwrap().twelve_hour);
+    }
+}
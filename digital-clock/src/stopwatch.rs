@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use termion::event::Key;
+
+use display;
+use display::Display;
+use Screen;
+
+
+const HELP: &str = "space start/stop   l lap   r reset   q quit";
+// only the most recent laps fit under the frame
+const SHOWN_LAPS: usize = 5;
+
+pub struct Stopwatch {
+    // when the stopwatch was last started, None while it is stopped
+    started: Option<Instant>,
+    // the time counted before it was last started
+    counted: Duration,
+    laps: Vec<Duration>,
+}
+
+impl Stopwatch {
+    pub fn new () -> Stopwatch {
+        Stopwatch { started: None, counted: Duration::from_secs(0), laps: Vec::new() }
+    }
+
+    fn elapsed (&self) -> Duration {
+        self.counted + self.started.map(|started| started.elapsed()).unwrap_or_default()
+    }
+
+    fn toggle (&mut self) {
+        match self.started.take() {
+            Some(started) => self.counted += started.elapsed(),
+            None => self.started = Some(Instant::now()),
+        }
+    }
+
+    fn lap (&mut self, display: &Display) {
+        if self.started.is_some() {
+            let elapsed = self.elapsed();
+            self.laps.push(elapsed);
+            self.show_laps(display);
+        }
+    }
+
+    fn reset (&mut self, display: &Display) {
+        *self = Stopwatch::new();
+        self.show_laps(display);
+    }
+
+    // the newest lap first, with the time since the lap before it
+    fn show_laps (&self, display: &Display) {
+        for row in 0..SHOWN_LAPS {
+            let line = 2 + row as u16;
+            let number = match self.laps.len().checked_sub(row) {
+                Some(number) if number > 0 => number,
+                _ => {
+                    display.show_line(line, "");
+                    continue;
+                },
+            };
+            let time = self.laps[number - 1];
+            let split = if number > 1 { time - self.laps[number - 2] } else { time };
+            display.show_line(line, &format!("lap {}  {}  +{}", number, display::format_duration(time),
+                                             display::format_duration(split)));
+        }
+    }
+}
+
+impl Screen for Stopwatch {
+    fn key (&mut self, key: Key, display: &Display) {
+        match key {
+            Key::Char(' ') => self.toggle(),
+            Key::Char('l') => self.lap(display),
+            Key::Char('r') => self.reset(display),
+            _ => {},
+        }
+    }
+
+    fn start (&mut self, display: &Display) {
+        display.show_line(0, HELP);
+        self.show_laps(display);
+    }
+
+    fn draw (&mut self, display: &Display) {
+        display.show_duration(self.elapsed());
+    }
+
+    // the help, a blank line and the laps
+    fn lines (&self) -> u16 {
+        2 + SHOWN_LAPS as u16
+    }
+}
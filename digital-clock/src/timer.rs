@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use termion::event::Key;
+
+use display::Display;
+use Screen;
+
+
+const HELP: &str = "space pause/resume   r restart   q quit";
+const DONE: &str = "time is up, space to stop ringing";
+
+pub struct Timer {
+    duration: Duration,
+    // when the countdown was last resumed, None while it is paused
+    resumed: Option<Instant>,
+    // what was left when it was last resumed
+    left: Duration,
+    // when the countdown reached zero, until space is pressed
+    rang: Option<Instant>,
+    bells: u64,
+}
+
+impl Timer {
+    pub fn new (duration: Duration) -> Timer {
+        Timer { duration, resumed: Some(Instant::now()), left: duration, rang: None, bells: 0 }
+    }
+
+    fn remaining (&self) -> Duration {
+        let running = self.resumed.map(|resumed| resumed.elapsed()).unwrap_or_default();
+        self.left.checked_sub(running).unwrap_or_default()
+    }
+
+    fn toggle (&mut self, display: &Display) {
+        if self.rang.take().is_some() {
+            display.show_line(0, HELP);
+            return;
+        }
+        let remaining = self.remaining();
+        match self.resumed.take() {
+            Some(_) => self.left = remaining,
+            None if self.left > Duration::from_secs(0) => self.resumed = Some(Instant::now()),
+            None => {},
+        }
+    }
+
+    fn restart (&mut self, display: &Display) {
+        *self = Timer::new(self.duration);
+        display.show_line(0, HELP);
+    }
+
+    // starts ringing when a running countdown has reached zero, true if it just did
+    fn expire (&mut self, remaining: Duration) -> bool {
+        if remaining == Duration::from_secs(0) && self.resumed.take().is_some() {
+            self.left = remaining;
+            self.rang = Some(Instant::now());
+            self.bells = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+}
+
+impl Screen for Timer {
+    fn key (&mut self, key: Key, display: &Display) {
+        match key {
+            Key::Char(' ') => self.toggle(display),
+            Key::Char('r') => self.restart(display),
+            _ => {},
+        }
+    }
+
+    fn start (&mut self, display: &Display) {
+        display.show_line(0, if self.rang.is_some() { DONE } else { HELP });
+    }
+
+    fn draw (&mut self, display: &Display) {
+        if let Some(rang) = self.rang {
+            return display.ring(rang, &mut self.bells, || display.show_duration(Duration::from_secs(0)));
+        }
+        let remaining = self.remaining();
+        display.show_duration(remaining);
+        if self.expire(remaining) {
+            display.show_line(0, DONE);
+        }
+    }
+
+    fn lines (&self) -> u16 {
+        1
+    }
+}
+
+// seconds as `90`, units as `1h30m` or `45s`, or a clock as `10:00` or `1:30:00`
+pub fn parse_duration (text: &str) -> Option<Duration> {
+    if text.is_empty() {
+        return None;
+    }
+    if text.contains(':') {
+        let mut seconds: u64 = 0;
+        for (i, part) in text.split(':').enumerate() {
+            let value: u64 = part.parse().ok()?;
+            if i > 2 || (i > 0 && value >= 60) {
+                return None;
+            }
+            seconds = seconds.checked_mul(60)?.checked_add(value)?;
+        }
+        return Some(Duration::from_secs(seconds));
+    }
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            },
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        seconds = seconds.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        seconds = seconds.checked_add(number.parse().ok()?)?;
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn seconds (text: &str) -> Option<u64> {
+        parse_duration(text).map(|duration| duration.as_secs())
+    }
+
+    #[test]
+    fn durations () {
+        assert_eq!(seconds("90"), Some(90));
+        assert_eq!(seconds("25m"), Some(25 * 60));
+        assert_eq!(seconds("1h30m"), Some(90 * 60));
+        assert_eq!(seconds("1m30"), Some(90));
+        assert_eq!(seconds("45s"), Some(45));
+        assert_eq!(seconds("10:00"), Some(10 * 60));
+        assert_eq!(seconds("1:30:00"), Some(90 * 60));
+        assert_eq!(seconds("0:05"), Some(5));
+        for text in ["", "m", "1x", "1.5m", "-1", "1:60", "1:2:3:4", "1::2", ":30", "10:"].iter() {
+            assert_eq!(seconds(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn overflowing_durations_are_invalid () {
+        assert_eq!(seconds("18446744073709551615"), Some(u64::MAX));
+        assert_eq!(seconds("18446744073709551616"), None);
+        assert_eq!(seconds("5124095576030432h"), None);
+        assert_eq!(seconds("307445734561825861m"), None);
+        assert_eq!(seconds("18446744073709551615s1"), None);
+        assert_eq!(seconds("307445734561825861:00"), None);
+    }
+
+    #[test]
+    fn countdowns_ring_once_they_reach_zero () {
+        let mut timer = Timer::new(Duration::from_secs(60));
+        assert!(!timer.expire(timer.remaining()));
+        assert!(timer.rang.is_none());
+
+        timer.resumed = Instant::now().checked_sub(Duration::from_secs(61));
+        assert_eq!(timer.remaining(), Duration::from_secs(0));
+        assert!(timer.expire(timer.remaining()));
+        assert!(timer.rang.is_some());
+        assert!(!timer.expire(timer.remaining()));
+    }
+
+    #[test]
+    fn paused_countdowns_never_ring () {
+        let mut timer = Timer::new(Duration::from_secs(60));
+        timer.resumed = None;
+        timer.left = Duration::from_secs(0);
+        assert!(!timer.expire(timer.remaining()));
+        assert!(timer.rang.is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use std::env;
+use std::path::PathBuf;
+
+use time;
+use time::{Duration, Tm};
+
+
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+// the timezone the clock shows
+pub enum Zone {
+    Local,
+    // an IANA name such as Europe/Paris, looked up by the C library through TZ
+    Named(String),
+    // minutes east of UTC
+    Offset(i32),
+}
+
+impl Zone {
+    // `UTC`, `+05:30`, `-0800`, `UTC+2` or a name from the tz database
+    pub fn parse (text: &str) -> Result<Zone, String> {
+        if let Some(offset) = parse_offset(text) {
+            return Ok(Zone::Offset(offset));
+        }
+        let path = zoneinfo_dir().join(text);
+        if text.starts_with('/') || text.split('/').any(|part| part == "..") || !path.is_file() {
+            return Err(format!("unknown timezone `{}`", text));
+        }
+        Ok(Zone::Named(text.to_string()))
+    }
+
+    // localtime reads TZ again after tzset, so this has to come before the first `now`
+    pub fn apply (&self) {
+        if let Zone::Named(ref name) = *self {
+            env::set_var("TZ", name);
+            time::tzset();
+        }
+    }
+
+    pub fn now (&self) -> Tm {
+        match *self {
+            Zone::Local | Zone::Named(_) => time::now(),
+            Zone::Offset(minutes) => time::at_utc(time::get_time() + Duration::minutes(minutes as i64)),
+        }
+    }
+
+    // how the zone is shown next to the date, nothing for the local time
+    pub fn label (&self) -> Option<String> {
+        match *self {
+            Zone::Local => None,
+            Zone::Named(ref name) => Some(name.clone()),
+            Zone::Offset(0) => Some("UTC".to_string()),
+            Zone::Offset(minutes) => {
+                let sign = if minutes < 0 { '-' } else { '+' };
+                Some(format!("UTC{}{:02}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60))
+            },
+        }
+    }
+}
+
+fn zoneinfo_dir () -> PathBuf {
+    env::var_os("TZDIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(ZONEINFO))
+}
+
+// the offset in minutes, with hours and minutes given as `5`, `05`, `0530` or `05:30`,
+// up to 14 hours either way
+fn parse_offset (text: &str) -> Option<i32> {
+    let text = text.strip_prefix("UTC").or_else(|| text.strip_prefix("GMT")).unwrap_or(text);
+    if text.is_empty() {
+        return Some(0);
+    }
+    let sign = match text.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return None,
+    };
+    let rest = &text[1..];
+    if !rest.chars().all(|c| c.is_ascii_digit() || c == ':') {
+        return None;
+    }
+    let (hours, minutes) = match rest.find(':') {
+        Some(i) if rest.len() - i == 3 => (&rest[..i], &rest[i + 1..]),
+        Some(_) => return None,
+        None if rest.len() > 2 => rest.split_at(rest.len() - 2),
+        None => (rest, "0"),
+    };
+    if hours.is_empty() || hours.len() > 2 {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if minutes >= 60 || hours * 60 + minutes > 14 * 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets () {
+        assert_eq!(parse_offset("+05:30"), Some(330));
+        assert_eq!(parse_offset("-0800"), Some(-480));
+        assert_eq!(parse_offset("+5"), Some(300));
+        assert_eq!(parse_offset("-530"), Some(-330));
+        assert_eq!(parse_offset("UTC"), Some(0));
+        assert_eq!(parse_offset("UTC+2"), Some(120));
+        assert_eq!(parse_offset("GMT-03:00"), Some(-180));
+        assert_eq!(parse_offset("+14:00"), Some(14 * 60));
+        assert_eq!(parse_offset("-00:45"), Some(-45));
+    }
+
+    #[test]
+    fn offsets_out_of_range_or_malformed () {
+        for text in ["+15", "-1500", "+14:30", "+05:60", "+0599", "+123:00", "+12345", "+1:3", "+12:5", "+05:30:00",
+                     "+05::30", "+:30", "+", "05:30", "+5h", "+٣", "UTC+", "UTCUTC+5",
+                     "UTCGMT+5", "GMTUTC", "Europe/Paris"].iter() {
+            assert_eq!(parse_offset(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn labels () {
+        assert_eq!(Zone::parse("+05:30").unwrap().label(), Some("UTC+05:30".to_string()));
+        assert_eq!(Zone::parse("UTC-8").unwrap().label(), Some("UTC-08:00".to_string()));
+        assert_eq!(Zone::parse("GMT").unwrap().label(), Some("UTC".to_string()));
+        assert!(Zone::parse("../etc/passwd").is_err());
+        assert!(Zone::parse("+15:00").is_err());
+    }
+}